@@ -1,41 +1,192 @@
-use std::{
-    hash::{Hash, Hasher},
-    mem::discriminant,
+use std::mem::{discriminant, Discriminant};
+
+use email::envelope::Flag;
+
+use crate::{
+    draft::{Mailbox, ReplyKind},
+    query::{Query, Sort},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Actions {
     ListFolders {
         login: String,
+        request_id: u64,
     },
     ListEnvelopes {
         login: String,
         folder: String,
         page: usize,
+        page_size: usize,
+        query: Option<Query>,
+        sort: Sort,
+        request_id: u64,
     },
     GetMessage {
         login: String,
         folder: String,
         id: String,
+        request_id: u64,
     },
     SendMessage {
         login: String,
-        to: String,
+        to: Vec<Mailbox>,
+        cc: Vec<Mailbox>,
+        bcc: Vec<Mailbox>,
         subject: String,
         text: String,
+        in_reply_to: Option<String>,
+        references: Vec<String>,
+    },
+    /// Appends a sent message to the Sent folder, SMTP does not keep a copy
+    SaveSentMessage {
+        login: String,
+        raw: Vec<u8>,
+    },
+    /// Fills the compose form with an answer to the message
+    PrepareDraft {
+        login: String,
+        folder: String,
+        id: String,
+        kind: ReplyKind,
+    },
+    AddFlag {
+        login: String,
+        folder: String,
+        ids: Vec<String>,
+        flag: Flag,
+    },
+    RemoveFlag {
+        login: String,
+        folder: String,
+        ids: Vec<String>,
+        flag: Flag,
+    },
+    MoveMessages {
+        login: String,
+        folder: String,
+        ids: Vec<String>,
+        target: MoveTarget,
+    },
+    /// Deletes for good instead of moving to the Trash
+    ExpungeMessages {
+        login: String,
+        folder: String,
+        ids: Vec<String>,
     },
 }
 
-impl PartialEq for Actions {
-    fn eq(&self, other: &Self) -> bool {
-        discriminant(self) == discriminant(other)
-    }
+/// Where a message is moved or saved, special folders are looked up on the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveTarget {
+    Folder(String),
+    Trash,
+    Archive,
+    Sent,
+}
+
+/// Identifies read actions that supersede each other
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionKey {
+    kind: Discriminant<Actions>,
+    login: String,
+    folder: Option<String>,
+}
+
+/// The pane of an account a read action fills, only its latest request may write it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestSlot {
+    kind: Discriminant<Actions>,
+    login: String,
 }
 
-impl Eq for Actions {}
+impl Actions {
+    pub fn login(&self) -> &str {
+        match self {
+            Actions::ListFolders { login, .. }
+            | Actions::ListEnvelopes { login, .. }
+            | Actions::GetMessage { login, .. }
+            | Actions::SendMessage { login, .. }
+            | Actions::SaveSentMessage { login, .. }
+            | Actions::PrepareDraft { login, .. }
+            | Actions::AddFlag { login, .. }
+            | Actions::RemoveFlag { login, .. }
+            | Actions::MoveMessages { login, .. }
+            | Actions::ExpungeMessages { login, .. } => login,
+        }
+    }
+
+    // Writes have no key and are never coalesced
+    pub fn key(&self) -> Option<ActionKey> {
+        let folder = match self {
+            Actions::ListFolders { .. } => None,
+            Actions::ListEnvelopes { folder, .. } | Actions::GetMessage { folder, .. } => {
+                Some(folder.clone())
+            }
+            Actions::SendMessage { .. }
+            | Actions::SaveSentMessage { .. }
+            | Actions::PrepareDraft { .. }
+            | Actions::AddFlag { .. }
+            | Actions::RemoveFlag { .. }
+            | Actions::MoveMessages { .. }
+            | Actions::ExpungeMessages { .. } => return None,
+        };
+
+        Some(ActionKey {
+            kind: discriminant(self),
+            login: self.login().to_string(),
+            folder,
+        })
+    }
+
+    pub fn request(&self) -> Option<(RequestSlot, u64)> {
+        let request_id = match self {
+            Actions::ListFolders { request_id, .. }
+            | Actions::ListEnvelopes { request_id, .. }
+            | Actions::GetMessage { request_id, .. } => *request_id,
+            Actions::SendMessage { .. }
+            | Actions::SaveSentMessage { .. }
+            | Actions::PrepareDraft { .. }
+            | Actions::AddFlag { .. }
+            | Actions::RemoveFlag { .. }
+            | Actions::MoveMessages { .. }
+            | Actions::ExpungeMessages { .. } => return None,
+        };
+
+        let slot = RequestSlot {
+            kind: discriminant(self),
+            login: self.login().to_string(),
+        };
+
+        Some((slot, request_id))
+    }
 
-impl Hash for Actions {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        discriminant(self).hash(state);
+    /// The flag change that undoes this one
+    pub fn flag_rollback(&self) -> Option<Actions> {
+        match self.clone() {
+            Actions::AddFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            } => Some(Actions::RemoveFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            }),
+            Actions::RemoveFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            } => Some(Actions::AddFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            }),
+            _ => None,
+        }
     }
 }
@@ -1,16 +1,29 @@
 use crate::{
-    actions::Actions,
-    state::{State, ViewState},
+    actions::{Actions, MoveTarget},
+    draft::{self, Draft, ReplyKind},
+    query::{self, Query, SavedQuery, Sort},
+    state::{Selection, SendStatus, State, ViewState},
     ui::ui,
 };
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind};
+use email::envelope::Flag;
 use ratatui::{
     widgets::{ListState, TableState},
     DefaultTerminal, Frame,
 };
-use std::{sync::Arc, time::Duration};
-use tokio::sync::mpsc::UnboundedSender;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::mpsc::UnboundedSender, time::interval};
+use tokio_stream::StreamExt;
+
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+
+// Until the messages table has been drawn once
+const DEFAULT_PAGE_SIZE: usize = 10;
 
 #[derive(Debug, Default, Eq, Hash, PartialEq)]
 pub enum SelectedWidget {
@@ -26,10 +39,19 @@ pub enum SelectedWidget {
 pub enum SelectedSendWidget {
     #[default]
     To,
+    Cc,
+    Bcc,
     Subject,
     Text,
 }
 
+#[derive(Debug, Eq, Hash, PartialEq)]
+pub enum Prompt {
+    Search,
+    SaveQuery,
+    ConfirmExpunge,
+}
+
 #[derive(Debug, Default)]
 pub struct App {
     pub selected_widget: SelectedWidget,
@@ -43,15 +65,45 @@ pub struct App {
 
     pub messages_table_state: TableState,
     pub messages_table_selected: Option<usize>,
+    /// Last page requested, ahead of the loaded ones while the next page is fetched
     pub messages_table_page: usize,
+    /// Folder and id of the message last requested for the Message window
+    pub opened_message: Option<(String, String)>,
+    /// Ids of the rows bulk actions apply to
+    pub marked_messages: HashSet<String>,
+    /// Row the visual range started at
+    pub visual_anchor: Option<usize>,
+    /// Rows the messages table fits, set by the ui on every draw
+    pub messages_table_rows: usize,
+    /// Configured page size, overrides the table height
+    pub page_size: Option<usize>,
+
+    pub search: Option<String>,
+    pub search_query: Option<Query>,
+    pub folder_sorts: HashMap<(String, String), Sort>,
+
+    pub prompt: Option<Prompt>,
+    /// Some while the folder picker of a move is open
+    pub move_picker_state: Option<ListState>,
+    pub prompt_input: String,
+    pub prompt_error: Option<String>,
 
     pub view_state: ViewState,
 
-    pub should_mark_state_as_updating: bool,
+    pub last_request_id: u64,
+    pub should_dismiss_notification: bool,
+    pub should_send: Option<Actions>,
+    pub should_save_query: Option<SavedQuery>,
 
     pub send_to: String,
+    pub send_cc: String,
+    pub send_bcc: String,
     pub send_subject: String,
     pub send_text: String,
+    pub send_in_reply_to: Option<String>,
+    pub send_references: Vec<String>,
+    pub is_awaiting_send: bool,
+    pub show_send_status: bool,
 
     pub exit: bool,
 }
@@ -64,56 +116,172 @@ impl App {
         actions_tx: UnboundedSender<Actions>,
     ) -> Result<()> {
         self.view_state = state.as_view_state(None).await;
+        self.page_size = state.page_size;
 
         self.select_first_account(actions_tx.clone());
 
-        while !self.exit {
-            if let Some(selected_account_idx) = self.accounts_list_selected {
-                let selected_account = self.view_state.accounts.get(selected_account_idx).cloned();
+        let mut events = EventStream::new();
+        let mut spinner = interval(SPINNER_TICK);
 
+        while !self.exit {
+            if self.accounts_list_selected.is_some() {
                 self.select_first_folder_if_not_selected(actions_tx.clone());
-                self.select_first_message_if_not_selected(actions_tx.clone());
+                self.select_first_message_if_not_selected();
+                self.open_selected_message(actions_tx.clone());
 
-                if !state.is_updating().await {
-                    self.view_state = state.as_view_state(selected_account).await;
+                // Panes that do not match the selection yet are shown as loading
+                if let Some(selection) = self.selection() {
+                    self.view_state = state.as_view_state(Some(selection)).await;
                 }
             };
 
+            self.handle_send_status();
+
             terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events(actions_tx.clone())?;
 
-            if self.should_mark_state_as_updating {
-                *state.message.write().await = None; // TODO: workaround for now
-                *state.is_updating.write().await = true;
+            // Sleeps until there is input, a backend update or a spinner to animate
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => self.handle_event(event?, actions_tx.clone()),
+                    None => self.exit(),
+                },
+                _ = state.changed() => {}
+                _ = spinner.tick(), if self.is_loading() => {}
+            }
+
+            // Marked before the dispatch, so a send rejected right away stays failed
+            if let Some(action) = self.should_send.take() {
+                *state.send_status.write().await = Some(SendStatus::Sending);
+                self.view_state.send_status = Some(SendStatus::Sending);
 
-                self.should_mark_state_as_updating = false;
+                let _ = actions_tx.send(action);
+            }
+
+            if let Some(saved) = self.should_save_query.take() {
+                let login = saved.login.clone();
+
+                if let Err(err) = state.save_query(saved).await {
+                    state
+                        .report_error(&login, format!("Cannot save query: {:#}", err))
+                        .await;
+                }
+            }
+
+            if let Some(draft) = state.draft.write().await.take() {
+                self.open_draft(draft);
+            }
+
+            if self.should_dismiss_notification {
+                state.dismiss_notification().await;
+                self.view_state.notification = None;
+
+                self.should_dismiss_notification = false;
             }
         }
 
         Ok(())
     }
 
+    fn selection(&self) -> Option<Selection> {
+        let login = self
+            .view_state
+            .accounts
+            .get(self.accounts_list_selected?)
+            .cloned()?;
+
+        let folder = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx));
+
+        let message_id = self
+            .messages_table_selected
+            .zip(self.view_state.messages.as_ref())
+            .and_then(|(idx, messages)| messages.get(idx))
+            .map(|e| e.id.clone());
+
+        let sort = folder
+            .as_ref()
+            .map(|folder| self.folder_sort(&login, folder))
+            .unwrap_or_default();
+
+        Some(Selection {
+            login,
+            folder,
+            query: self.search_query.clone(),
+            sort,
+            message_id,
+        })
+    }
+
     fn draw(&mut self, frame: &mut Frame<'_>) {
         ui(frame, self);
     }
 
-    fn handle_events(&mut self, actions_tx: UnboundedSender<Actions>) -> Result<()> {
-        if event::poll(Duration::from_millis(10))? {
-            match event::read()? {
-                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                    self.handle_key_event(key_event, actions_tx)
+    // Whether any pane draws a throbber, only then the spinner has to tick
+    fn is_loading(&self) -> bool {
+        self.is_loading_folders() || self.is_loading_messages() || self.is_loading_message()
+    }
+
+    fn handle_event(&mut self, event: Event, actions_tx: UnboundedSender<Actions>) {
+        if let Event::Key(key_event) = event {
+            if key_event.kind == KeyEventKind::Press {
+                self.handle_key_event(key_event, actions_tx)
+            }
+        }
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent, actions_tx: UnboundedSender<Actions>) {
+        if self.prompt == Some(Prompt::ConfirmExpunge) {
+            if key_event.code == KeyCode::Char('y') {
+                self.expunge_messages(actions_tx);
+            }
+
+            self.close_prompt();
+            return;
+        }
+
+        if let Some(prompt) = &self.prompt {
+            match key_event.code {
+                KeyCode::Esc => self.close_prompt(),
+                KeyCode::Enter => match prompt {
+                    Prompt::Search => self.submit_search(actions_tx),
+                    Prompt::SaveQuery => self.submit_saved_query(),
+                    Prompt::ConfirmExpunge => {}
+                },
+                KeyCode::Char(value) => self.prompt_input.push(value),
+                KeyCode::Backspace => {
+                    let _ = self.prompt_input.pop();
                 }
                 _ => {}
-            };
+            }
+
+            return;
         }
 
-        Ok(())
-    }
+        if let Some(picker) = &mut self.move_picker_state {
+            let folders_len = self.view_state.folders.as_ref().map_or(0, |f| f.len());
+
+            match key_event.code {
+                KeyCode::Esc => self.move_picker_state = None,
+                KeyCode::Enter => self.submit_move_picker(actions_tx),
+                KeyCode::Up | KeyCode::Char('k') => picker.select(
+                    picker
+                        .selected()
+                        .map(|idx| (idx + folders_len - 1) % folders_len),
+                ),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    picker.select(picker.selected().map(|idx| (idx + 1) % folders_len))
+                }
+                _ => {}
+            }
+
+            return;
+        }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent, actions_tx: UnboundedSender<Actions>) {
         match self.selected_widget {
             SelectedWidget::Accounts => match key_event.code {
                 KeyCode::Char('q') => self.exit(),
+                KeyCode::Esc => self.dismiss_notification(),
                 KeyCode::Char('x') => Self::ads_remove(),
                 KeyCode::Char('2') => self.select_folders_widget(),
                 KeyCode::Char('3') => self.select_messages_widget(),
@@ -125,6 +293,7 @@ impl App {
             },
             SelectedWidget::Folders => match key_event.code {
                 KeyCode::Char('q') => self.exit(),
+                KeyCode::Esc => self.dismiss_notification(),
                 KeyCode::Char('x') => Self::ads_remove(),
                 KeyCode::Char('1') => self.select_accounts_widget(),
                 KeyCode::Char('3') => self.select_messages_widget(),
@@ -136,65 +305,60 @@ impl App {
             },
             SelectedWidget::Messages => match key_event.code {
                 KeyCode::Char('q') => self.exit(),
+                KeyCode::Esc => {
+                    self.dismiss_notification();
+                    self.clear_marks();
+                }
                 KeyCode::Char('x') => Self::ads_remove(),
                 KeyCode::Char('1') => self.select_accounts_widget(),
                 KeyCode::Char('2') => self.select_folders_widget(),
                 KeyCode::Char('4') => self.select_message_widget(),
                 KeyCode::Char('s') => self.select_send_widget(),
-                KeyCode::Up | KeyCode::Char('k') => self.select_previous_message(actions_tx),
+                KeyCode::Up | KeyCode::Char('k') => self.select_previous_message(),
                 KeyCode::Down | KeyCode::Char('j') => self.select_next_message(actions_tx),
-                KeyCode::Left | KeyCode::Char('p') => self.select_previous_message_page(actions_tx),
-                KeyCode::Right | KeyCode::Char('n') => self.select_next_message_page(actions_tx),
+                KeyCode::Char('/') => self.edit_search(),
+                KeyCode::Char('S') => self.edit_saved_query(),
+                KeyCode::Char('o') => self.toggle_sort(actions_tx),
+                KeyCode::Char('u') => self.toggle_flag(Flag::Seen, actions_tx),
+                KeyCode::Char('f') => self.toggle_flag(Flag::Flagged, actions_tx),
+                KeyCode::Char('a') => self.toggle_flag(Flag::Answered, actions_tx),
+                KeyCode::Char('d') => self.move_messages(MoveTarget::Trash, actions_tx),
+                KeyCode::Char('D') => self.confirm_expunge(),
+                KeyCode::Char('e') => self.move_messages(MoveTarget::Archive, actions_tx),
+                KeyCode::Char('m') => self.open_move_picker(),
+                KeyCode::Char(' ') => self.toggle_mark(),
+                KeyCode::Char('v') => self.toggle_visual(),
+                KeyCode::Char('*') => self.toggle_mark_all(),
                 _ => {}
             },
             SelectedWidget::Message => match key_event.code {
                 KeyCode::Char('q') => self.exit(),
+                KeyCode::Esc => self.dismiss_notification(),
                 KeyCode::Char('x') => Self::ads_remove(),
                 KeyCode::Char('1') => self.select_accounts_widget(),
                 KeyCode::Char('2') => self.select_folders_widget(),
                 KeyCode::Char('3') => self.select_messages_widget(),
                 KeyCode::Char('s') => self.select_send_widget(),
+                KeyCode::Char('r') => self.prepare_draft(ReplyKind::Reply, actions_tx),
+                KeyCode::Char('R') => self.prepare_draft(ReplyKind::ReplyAll, actions_tx),
+                KeyCode::Char('f') => self.prepare_draft(ReplyKind::Forward, actions_tx),
                 _ => {}
             },
             // TODO: refactor
             SelectedWidget::Send => match key_event.code {
                 KeyCode::Esc => {
-                    self.send_to = String::default();
-                    self.send_text = String::default();
-                    self.send_subject = String::default();
-                    self.select_accounts_widget()
-                }
-                KeyCode::Enter => {
-                    self.send_email(actions_tx.clone());
-                    self.send_to = String::default();
-                    self.send_text = String::default();
-                    self.send_subject = String::default();
+                    self.clear_send_form();
+                    self.show_send_status = false;
+                    self.is_awaiting_send = false;
                     self.select_accounts_widget()
                 }
+                KeyCode::Enter => self.send_email(),
                 KeyCode::Tab => self.select_next_send_widget(),
                 KeyCode::BackTab => self.select_previous_send_widget(),
-                KeyCode::Char(value) => match self.selected_send_widget {
-                    SelectedSendWidget::To => self.send_to.push(value),
-                    SelectedSendWidget::Subject => self.send_subject.push(value),
-                    SelectedSendWidget::Text => self.send_text.push(value),
-                },
-                KeyCode::Backspace => match self.selected_send_widget {
-                    SelectedSendWidget::To => {
-                        if !self.send_to.is_empty() {
-                            let _ = self.send_to.pop();
-                        }
-                    }
-                    SelectedSendWidget::Subject => {
-                        if !self.send_subject.is_empty() {
-                            let _ = self.send_subject.pop();
-                        }
-                    }
-                    SelectedSendWidget::Text => {
-                        if !self.send_text.is_empty() {
-                            let _ = self.send_text.pop();
-                        }
-                    }
-                },
+                KeyCode::Char(value) => self.send_input().push(value),
+                KeyCode::Backspace => {
+                    let _ = self.send_input().pop();
+                }
                 _ => {}
             },
         }
@@ -223,19 +387,27 @@ impl App {
     fn select_previous_send_widget(&mut self) {
         match self.selected_send_widget {
             SelectedSendWidget::To => self.selected_send_widget = SelectedSendWidget::Text,
-            SelectedSendWidget::Subject => self.selected_send_widget = SelectedSendWidget::To,
+            SelectedSendWidget::Cc => self.selected_send_widget = SelectedSendWidget::To,
+            SelectedSendWidget::Bcc => self.selected_send_widget = SelectedSendWidget::Cc,
+            SelectedSendWidget::Subject => self.selected_send_widget = SelectedSendWidget::Bcc,
             SelectedSendWidget::Text => self.selected_send_widget = SelectedSendWidget::Subject,
         }
     }
 
     fn select_next_send_widget(&mut self) {
         match self.selected_send_widget {
-            SelectedSendWidget::To => self.selected_send_widget = SelectedSendWidget::Subject,
+            SelectedSendWidget::To => self.selected_send_widget = SelectedSendWidget::Cc,
+            SelectedSendWidget::Cc => self.selected_send_widget = SelectedSendWidget::Bcc,
+            SelectedSendWidget::Bcc => self.selected_send_widget = SelectedSendWidget::Subject,
             SelectedSendWidget::Subject => self.selected_send_widget = SelectedSendWidget::Text,
             SelectedSendWidget::Text => self.selected_send_widget = SelectedSendWidget::To,
         }
     }
 
+    fn dismiss_notification(&mut self) {
+        self.should_dismiss_notification = true;
+    }
+
     fn exit(&mut self) {
         self.exit = true;
     }
@@ -252,8 +424,11 @@ impl App {
             let selected_account = self.view_state.accounts.get(selected_account_idx).cloned();
 
             if let Some(login) = selected_account {
-                self.should_mark_state_as_updating = true;
-                let _ = actions_tx.send(Actions::ListFolders { login });
+                self.last_request_id += 1;
+                let _ = actions_tx.send(Actions::ListFolders {
+                    login,
+                    request_id: self.last_request_id,
+                });
             }
         };
     }
@@ -278,8 +453,11 @@ impl App {
         let selected_account = self.view_state.accounts.get(previous_account_idx).cloned();
 
         if let Some(login) = selected_account {
-            self.should_mark_state_as_updating = true;
-            let _ = actions_tx.send(Actions::ListFolders { login });
+            self.last_request_id += 1;
+            let _ = actions_tx.send(Actions::ListFolders {
+                login,
+                request_id: self.last_request_id,
+            });
         }
     }
 
@@ -303,8 +481,11 @@ impl App {
         let selected_account = self.view_state.accounts.get(next_account_idx).cloned();
 
         if let Some(login) = selected_account {
-            self.should_mark_state_as_updating = true;
-            let _ = actions_tx.send(Actions::ListFolders { login });
+            self.last_request_id += 1;
+            let _ = actions_tx.send(Actions::ListFolders {
+                login,
+                request_id: self.last_request_id,
+            });
         }
     }
 
@@ -339,11 +520,17 @@ impl App {
         self.clear_messages();
         self.clear_message();
 
-        self.should_mark_state_as_updating = true;
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
         let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
             page: 0,
+            page_size: self.page_size(),
+            query: None,
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
@@ -360,17 +547,13 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
-            return;
-        };
-
         let previous_folder_idx = if selected_folder_idx == 0 {
-            folders.len() - 1
+            self.folders_len() - 1
         } else {
             selected_folder_idx - 1
         };
 
-        let Some(folder) = folders.get(previous_folder_idx).cloned() else {
+        let Some(folder) = self.folder_at(previous_folder_idx) else {
             return;
         };
 
@@ -380,11 +563,19 @@ impl App {
         self.clear_messages();
         self.clear_message();
 
-        self.should_mark_state_as_updating = true;
+        self.select_saved_query(previous_folder_idx);
+
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
         let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
             page: 0,
+            page_size: self.page_size(),
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
@@ -401,17 +592,13 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
-            return;
-        };
-
-        let next_folder_idx = if selected_folder_idx == folders.len() - 1 {
+        let next_folder_idx = if selected_folder_idx + 1 >= self.folders_len() {
             0
         } else {
             selected_folder_idx + 1
         };
 
-        let Some(folder) = folders.get(next_folder_idx).cloned() else {
+        let Some(folder) = self.folder_at(next_folder_idx) else {
             return;
         };
 
@@ -421,25 +608,42 @@ impl App {
         self.clear_messages();
         self.clear_message();
 
-        self.should_mark_state_as_updating = true;
+        self.select_saved_query(next_folder_idx);
+
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
         let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
             page: 0,
+            page_size: self.page_size(),
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
-    fn select_first_message_if_not_selected(&mut self, actions_tx: UnboundedSender<Actions>) {
-        if self.messages_table_selected.is_none() && self.view_state.messages.is_some() {
-            self.messages_table_selected = Some(0);
-            self.messages_table_state
-                .select(self.messages_table_selected);
-        } else {
+    fn select_first_message_if_not_selected(&mut self) {
+        let Some(messages) = &self.view_state.messages else {
             return;
+        };
+
+        // Rows can disappear under the selection when the listing is refreshed
+        match self.messages_table_selected {
+            None => self.messages_table_selected = Some(0),
+            Some(idx) if idx >= messages.len() => {
+                self.messages_table_selected = Some(messages.len().saturating_sub(1))
+            }
+            Some(_) => return,
         }
 
-        self.clear_message();
+        self.messages_table_state
+            .select(self.messages_table_selected);
+    }
 
+    // Opens whichever message is selected, also when another one moved under the selection
+    fn open_selected_message(&mut self, actions_tx: UnboundedSender<Actions>) {
         let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
@@ -448,39 +652,41 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
-            return;
-        };
-
-        let Some(selected_folder_idx) = self.folders_list_selected else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        let Some(folder) = folders.get(selected_folder_idx).cloned() else {
+        let Some(id) = self
+            .messages_table_selected
+            .zip(self.view_state.messages.as_ref())
+            .and_then(|(idx, messages)| messages.get(idx))
+            .map(|e| e.id.clone())
+        else {
             return;
         };
 
-        let Some(messages) = &self.view_state.messages else {
-            return;
-        };
+        let opened = Some((folder.clone(), id.clone()));
 
-        let Some(selected_message_idx) = self.messages_table_selected else {
+        if self.opened_message == opened {
             return;
-        };
+        }
 
-        let Some(envelope) = messages.get(selected_message_idx) else {
-            return;
-        };
+        self.opened_message = opened;
+        self.clear_message();
 
-        self.should_mark_state_as_updating = true;
+        self.last_request_id += 1;
         let _ = actions_tx.send(Actions::GetMessage {
             login,
             folder,
-            id: envelope.id.clone(),
+            id,
+            request_id: self.last_request_id,
         });
     }
 
-    fn select_previous_message(&mut self, actions_tx: UnboundedSender<Actions>) {
+    fn select_previous_message(&mut self) {
         let Some(selected_message_idx) = self.messages_table_selected else {
             return;
         };
@@ -502,73 +708,153 @@ impl App {
         self.messages_table_selected = Some(previous_message_idx);
         self.messages_table_state
             .select(self.messages_table_selected);
+    }
 
-        self.clear_message();
-
-        let Some(selected_account_idx) = self.accounts_list_selected else {
+    fn select_next_message(&mut self, actions_tx: UnboundedSender<Actions>) {
+        let Some(selected_message_idx) = self.messages_table_selected else {
             return;
         };
 
-        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
+        let Some(messages) = &self.view_state.messages else {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
+        if messages.is_empty() {
             return;
-        };
+        }
 
-        let Some(selected_folder_idx) = self.folders_list_selected else {
+        if selected_message_idx == messages.len() - 1 && self.view_state.has_more_messages {
+            self.load_next_message_page(actions_tx);
             return;
+        }
+
+        let next_message_idx = if selected_message_idx == messages.len() - 1 {
+            0
+        } else {
+            selected_message_idx + 1
         };
 
-        let Some(folder) = folders.get(selected_folder_idx).cloned() else {
+        self.messages_table_selected = Some(next_message_idx);
+        self.messages_table_state
+            .select(self.messages_table_selected);
+    }
+
+    // Appends to the table, the selection stays on the last row until the page arrives
+    fn load_next_message_page(&mut self, actions_tx: UnboundedSender<Actions>) {
+        if self.messages_table_page >= self.view_state.messages_pages {
             return;
-        };
+        }
 
-        let Some(messages) = &self.view_state.messages else {
+        let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
 
-        let Some(selected_message_idx) = self.messages_table_selected else {
+        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
             return;
         };
 
-        let Some(envelope) = messages.get(selected_message_idx) else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        self.should_mark_state_as_updating = true;
-        let _ = actions_tx.send(Actions::GetMessage {
+        self.messages_table_page = self.view_state.messages_pages;
+
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
+        let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
-            id: envelope.id.clone(),
+            page: self.messages_table_page,
+            page_size: self.view_state.messages_page_size,
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
-    fn select_next_message(&mut self, actions_tx: UnboundedSender<Actions>) {
-        let Some(selected_message_idx) = self.messages_table_selected else {
-            return;
-        };
+    /// Rows fetched per page of new listings
+    pub fn page_size(&self) -> usize {
+        self.page_size
+            .or(Some(self.messages_table_rows).filter(|r| *r > 0))
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+    }
 
-        let Some(messages) = &self.view_state.messages else {
-            return;
-        };
+    /// Whether the folders of the selected account were not listed yet
+    pub fn is_loading_folders(&self) -> bool {
+        self.view_state.error.is_none()
+            && self.accounts_list_selected.is_some()
+            && self.view_state.folders.is_none()
+    }
 
-        if messages.is_empty() {
+    /// Whether the envelopes of the selected folder were not listed yet
+    pub fn is_loading_messages(&self) -> bool {
+        self.view_state.error.is_none()
+            && self.folders_list_selected.is_some()
+            && self.view_state.messages.is_none()
+    }
+
+    /// Whether the selected message was not read yet
+    pub fn is_loading_message(&self) -> bool {
+        self.view_state.error.is_none()
+            && self.view_state.message.is_none()
+            && self
+                .selection()
+                .is_some_and(|selection| selection.message_id.is_some())
+    }
+
+    /// Whether the page after the loaded ones was requested but not shown yet
+    pub fn is_loading_more_messages(&self) -> bool {
+        self.view_state.messages.is_some()
+            && self.messages_table_page >= self.view_state.messages_pages
+    }
+
+    fn clear_folders(&mut self) {
+        self.folders_list_state = ListState::default();
+        self.folders_list_selected = None;
+        self.view_state.folders = None;
+    }
+
+    fn edit_search(&mut self) {
+        self.prompt_input = self.search.clone().unwrap_or_default();
+        self.prompt = Some(Prompt::Search);
+    }
+
+    // Only a search that is shown can be saved
+    fn edit_saved_query(&mut self) {
+        if self.search.is_none() {
             return;
         }
 
-        let next_message_idx = if selected_message_idx == messages.len() - 1 {
-            0
+        self.prompt_input = String::default();
+        self.prompt = Some(Prompt::SaveQuery);
+    }
+
+    fn close_prompt(&mut self) {
+        self.prompt = None;
+        self.prompt_error = None;
+    }
+
+    // An empty search goes back to the whole folder
+    fn submit_search(&mut self, actions_tx: UnboundedSender<Actions>) {
+        let text = self.prompt_input.trim().to_string();
+
+        let search_query = if text.is_empty() {
+            None
         } else {
-            selected_message_idx + 1
+            match query::parse(&text) {
+                Ok(search_query) => Some(search_query),
+                Err(err) => {
+                    self.prompt_error = Some(format!("{:#}", err));
+                    return;
+                }
+            }
         };
 
-        self.messages_table_selected = Some(next_message_idx);
-        self.messages_table_state
-            .select(self.messages_table_selected);
-
-        self.clear_message();
+        self.close_prompt();
 
         let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
@@ -578,39 +864,240 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        let Some(selected_folder_idx) = self.folders_list_selected else {
+        self.clear_messages();
+        self.clear_message();
+
+        self.search = Some(text).filter(|t| !t.is_empty());
+        self.search_query = search_query;
+
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
+        let _ = actions_tx.send(Actions::ListEnvelopes {
+            login,
+            folder,
+            page: 0,
+            page_size: self.page_size(),
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
+        });
+    }
+
+    fn submit_saved_query(&mut self) {
+        let name = self.prompt_input.trim().to_string();
+
+        if name.is_empty() {
+            return;
+        }
+
+        self.close_prompt();
+
+        let Some(login) = self
+            .accounts_list_selected
+            .and_then(|idx| self.view_state.accounts.get(idx).cloned())
+        else {
             return;
         };
 
-        let Some(folder) = folders.get(selected_folder_idx).cloned() else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        let Some(messages) = &self.view_state.messages else {
+        let Some(query) = self.search.clone() else {
             return;
         };
 
-        let Some(selected_message_idx) = self.messages_table_selected else {
+        self.should_save_query = Some(SavedQuery {
+            login,
+            name,
+            folder,
+            query,
+        });
+    }
+
+    // Saved queries are listed after the folders of the account and search
+    // the folder they were saved from
+    fn folder_at(&self, idx: usize) -> Option<String> {
+        let folders = self.view_state.folders.as_ref()?;
+
+        match folders.get(idx) {
+            Some(folder) => Some(folder.clone()),
+            None => self.saved_query_at(idx).map(|q| q.folder.clone()),
+        }
+    }
+
+    fn saved_query_at(&self, idx: usize) -> Option<&SavedQuery> {
+        let folders = self.view_state.folders.as_ref()?;
+
+        self.view_state
+            .saved_queries
+            .get(idx.checked_sub(folders.len())?)
+    }
+
+    /// Sort of the messages table, none when the search has its own `sort:`
+    pub fn table_sort(&self) -> Option<Sort> {
+        if self
+            .search_query
+            .as_ref()
+            .is_some_and(|q| q.search.sort.is_some())
+        {
+            return None;
+        }
+
+        self.selection().map(|s| s.sort)
+    }
+
+    pub fn folder_sort(&self, login: &str, folder: &str) -> Sort {
+        self.folder_sorts
+            .get(&(login.to_string(), folder.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Keeps the search, only the order of the results changes
+    fn toggle_sort(&mut self, actions_tx: UnboundedSender<Actions>) {
+        let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
 
-        let Some(envelope) = messages.get(selected_message_idx) else {
+        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
             return;
         };
 
-        self.should_mark_state_as_updating = true;
-        let _ = actions_tx.send(Actions::GetMessage {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
+            return;
+        };
+
+        let sort = self.folder_sort(&login, &folder).next();
+        self.folder_sorts
+            .insert((login.clone(), folder.clone()), sort);
+
+        self.messages_table_state = TableState::default();
+        self.messages_table_selected = None;
+        self.messages_table_page = 0;
+        self.view_state.messages = None;
+        self.clear_message();
+
+        self.last_request_id += 1;
+        let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
-            id: envelope.id.clone(),
+            page: 0,
+            page_size: self.page_size(),
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
-    fn select_previous_message_page(&mut self, actions_tx: UnboundedSender<Actions>) {
+    /// Whether the row is marked, or inside the visual range being selected
+    pub fn is_marked(&self, idx: usize) -> bool {
+        let is_in_range = self
+            .visual_anchor
+            .zip(self.messages_table_selected)
+            .is_some_and(|(anchor, selected)| {
+                (anchor.min(selected)..=anchor.max(selected)).contains(&idx)
+            });
+
+        is_in_range
+            || self
+                .view_state
+                .messages
+                .as_ref()
+                .and_then(|messages| messages.get(idx))
+                .is_some_and(|e| self.marked_messages.contains(&e.id))
+    }
+
+    /// Rows an action applies to, the selected one when none is marked
+    pub fn target_rows(&self) -> Vec<usize> {
+        let Some(messages) = &self.view_state.messages else {
+            return Vec::new();
+        };
+
+        let rows: Vec<usize> = (0..messages.len())
+            .filter(|idx| self.is_marked(*idx))
+            .collect();
+
+        if !rows.is_empty() {
+            return rows;
+        }
+
+        self.messages_table_selected
+            .filter(|idx| *idx < messages.len())
+            .into_iter()
+            .collect()
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(id) = self
+            .messages_table_selected
+            .zip(self.view_state.messages.as_ref())
+            .and_then(|(idx, messages)| messages.get(idx))
+            .map(|e| e.id.clone())
+        else {
+            return;
+        };
+
+        if !self.marked_messages.remove(&id) {
+            self.marked_messages.insert(id);
+        }
+    }
+
+    // The range follows the selection until v is pressed again
+    fn toggle_visual(&mut self) {
+        if self.visual_anchor.is_none() {
+            self.visual_anchor = self.messages_table_selected;
+            return;
+        }
+
+        for idx in self.target_rows() {
+            if let Some(envelope) = self.view_state.messages.as_ref().and_then(|m| m.get(idx)) {
+                self.marked_messages.insert(envelope.id.clone());
+            }
+        }
+
+        self.visual_anchor = None;
+    }
+
+    // Marks every loaded row, or unmarks them when they all are
+    fn toggle_mark_all(&mut self) {
+        let Some(messages) = &self.view_state.messages else {
+            return;
+        };
+
+        if messages
+            .iter()
+            .all(|e| self.marked_messages.contains(&e.id))
+        {
+            self.marked_messages.clear();
+        } else {
+            self.marked_messages
+                .extend(messages.iter().map(|e| e.id.clone()));
+        }
+
+        self.visual_anchor = None;
+    }
+
+    fn clear_marks(&mut self) {
+        self.marked_messages.clear();
+        self.visual_anchor = None;
+    }
+
+    fn toggle_flag(&mut self, flag: Flag, actions_tx: UnboundedSender<Actions>) {
         let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
@@ -619,37 +1106,99 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        let Some(selected_folder_idx) = self.folders_list_selected else {
+        let Some(messages) = &self.view_state.messages else {
             return;
         };
 
-        let Some(folder) = folders.get(selected_folder_idx).cloned() else {
+        let envelopes: Vec<_> = self
+            .target_rows()
+            .into_iter()
+            .filter_map(|idx| messages.get(idx))
+            .collect();
+
+        if envelopes.is_empty() {
+            return;
+        }
+
+        // Mixed rows all get the flag, a second press then removes it from all
+        let is_set = envelopes.iter().all(|e| e.flags.contains(&flag));
+
+        // Only rows that change are sent, so a rollback leaves the others alone
+        let ids = envelopes
+            .iter()
+            .filter(|e| e.flags.contains(&flag) == is_set)
+            .map(|e| e.id.clone())
+            .collect();
+
+        let _ = actions_tx.send(if is_set {
+            Actions::RemoveFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            }
+        } else {
+            Actions::AddFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            }
+        });
+
+        self.clear_marks();
+    }
+
+    fn move_messages(&mut self, target: MoveTarget, actions_tx: UnboundedSender<Actions>) {
+        let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
 
-        if self.messages_table_page == 0 {
+        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
             return;
-        } else {
-            self.messages_table_page -= 1;
         };
 
-        self.messages_table_state = TableState::default();
-        self.messages_table_selected = None;
-        self.view_state.messages = None;
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
+            return;
+        };
 
-        self.should_mark_state_as_updating = true;
-        let _ = actions_tx.send(Actions::ListEnvelopes {
+        let rows = self.target_rows();
+        let ids = self.row_ids(&rows);
+
+        if ids.is_empty() {
+            return;
+        }
+
+        let _ = actions_tx.send(Actions::MoveMessages {
             login,
             folder,
-            page: self.messages_table_page,
+            ids,
+            target,
         });
+
+        self.clear_marks();
+        self.refresh_messages_page(rows[0], actions_tx);
+    }
+
+    fn confirm_expunge(&mut self) {
+        if self.target_rows().is_empty() {
+            return;
+        }
+
+        self.prompt = Some(Prompt::ConfirmExpunge);
     }
 
-    fn select_next_message_page(&mut self, actions_tx: UnboundedSender<Actions>) {
+    fn expunge_messages(&mut self, actions_tx: UnboundedSender<Actions>) {
         let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
@@ -658,57 +1207,213 @@ impl App {
             return;
         };
 
-        let Some(folders) = &self.view_state.folders else {
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        let Some(selected_folder_idx) = self.folders_list_selected else {
+        let rows = self.target_rows();
+        let ids = self.row_ids(&rows);
+
+        if ids.is_empty() {
             return;
+        }
+
+        let _ = actions_tx.send(Actions::ExpungeMessages { login, folder, ids });
+
+        self.clear_marks();
+        self.refresh_messages_page(rows[0], actions_tx);
+    }
+
+    fn row_ids(&self, rows: &[usize]) -> Vec<String> {
+        let Some(messages) = &self.view_state.messages else {
+            return Vec::new();
         };
 
-        let Some(folder) = folders.get(selected_folder_idx).cloned() else {
+        rows.iter()
+            .filter_map(|idx| messages.get(*idx))
+            .map(|e| e.id.clone())
+            .collect()
+    }
+
+    // Only real folders can be picked, saved queries are not a destination
+    fn open_move_picker(&mut self) {
+        if self.target_rows().is_empty()
+            || self
+                .view_state
+                .folders
+                .as_ref()
+                .is_none_or(|f| f.is_empty())
+        {
+            return;
+        }
+
+        self.move_picker_state = Some(ListState::default().with_selected(Some(0)));
+    }
+
+    fn submit_move_picker(&mut self, actions_tx: UnboundedSender<Actions>) {
+        let Some(picker) = self.move_picker_state.take() else {
             return;
         };
 
-        let Some(messages) = &self.view_state.messages else {
+        let Some(to) = picker
+            .selected()
+            .zip(self.view_state.folders.as_ref())
+            .and_then(|(idx, folders)| folders.get(idx).cloned())
+        else {
             return;
         };
 
-        if messages.len() == 10 {
-            self.messages_table_state = TableState::default();
-            self.messages_table_selected = None;
-            self.view_state.messages = None;
-            self.messages_table_page += 1;
-        } else {
+        self.move_messages(MoveTarget::Folder(to), actions_tx);
+    }
+
+    // Lists the page of the row again, it replaces that page and the ones
+    // after it. Being queued after a move, it sees the messages gone.
+    fn refresh_messages_page(&mut self, row: usize, actions_tx: UnboundedSender<Actions>) {
+        let Some(selected_account_idx) = self.accounts_list_selected else {
+            return;
+        };
+
+        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
+            return;
+        };
+
+        let Some(folder) = self
+            .folders_list_selected
+            .and_then(|idx| self.folder_at(idx))
+        else {
             return;
         };
 
-        self.should_mark_state_as_updating = true;
+        let page_size = self.view_state.messages_page_size.max(1);
+        self.messages_table_page = row / page_size;
+
+        let sort = self.folder_sort(&login, &folder);
+
+        self.last_request_id += 1;
         let _ = actions_tx.send(Actions::ListEnvelopes {
             login,
             folder,
             page: self.messages_table_page,
+            page_size,
+            query: self.search_query.clone(),
+            sort,
+            request_id: self.last_request_id,
         });
     }
 
-    fn clear_folders(&mut self) {
-        self.folders_list_state = ListState::default();
-        self.folders_list_selected = None;
-        self.view_state.folders = None;
+    fn select_saved_query(&mut self, idx: usize) {
+        let Some(text) = self.saved_query_at(idx).map(|q| q.query.clone()) else {
+            return;
+        };
+
+        self.search_query = query::parse(&text).ok();
+        self.search = Some(text);
+    }
+
+    fn folders_len(&self) -> usize {
+        self.view_state.folders.as_ref().map_or(0, |f| f.len())
+            + self.view_state.saved_queries.len()
     }
 
     fn clear_messages(&mut self) {
         self.messages_table_state = TableState::default();
         self.messages_table_selected = None;
         self.messages_table_page = 0;
+        self.opened_message = None;
+        self.clear_marks();
         self.view_state.messages = None;
+        self.search = None;
+        self.search_query = None;
     }
 
     fn clear_message(&mut self) {
         self.view_state.message = None;
     }
 
-    fn send_email(&mut self, actions_tx: UnboundedSender<Actions>) {
+    fn send_input(&mut self) -> &mut String {
+        match self.selected_send_widget {
+            SelectedSendWidget::To => &mut self.send_to,
+            SelectedSendWidget::Cc => &mut self.send_cc,
+            SelectedSendWidget::Bcc => &mut self.send_bcc,
+            SelectedSendWidget::Subject => &mut self.send_subject,
+            SelectedSendWidget::Text => &mut self.send_text,
+        }
+    }
+
+    fn clear_send_form(&mut self) {
+        self.send_to = String::default();
+        self.send_cc = String::default();
+        self.send_bcc = String::default();
+        self.send_subject = String::default();
+        self.send_text = String::default();
+        self.send_in_reply_to = None;
+        self.send_references = Vec::new();
+    }
+
+    // Every address has to parse and there has to be someone to send to
+    pub fn is_send_form_valid(&self) -> bool {
+        let mut recipients = 0;
+
+        for text in [&self.send_to, &self.send_cc, &self.send_bcc] {
+            let Ok(mailboxes) = draft::parse_recipients(text) else {
+                return false;
+            };
+
+            recipients += mailboxes.len();
+        }
+
+        recipients > 0
+    }
+
+    // The backend fetches the original, the draft is taken from the state once ready
+    fn prepare_draft(&mut self, kind: ReplyKind, actions_tx: UnboundedSender<Actions>) {
+        let Some(selected_account_idx) = self.accounts_list_selected else {
+            return;
+        };
+
+        let Some(login) = self.view_state.accounts.get(selected_account_idx).cloned() else {
+            return;
+        };
+
+        let Some((folder, id)) = self.opened_message.clone() else {
+            return;
+        };
+
+        let _ = actions_tx.send(Actions::PrepareDraft {
+            login,
+            folder,
+            id,
+            kind,
+        });
+    }
+
+    fn open_draft(&mut self, draft: Draft) {
+        self.selected_send_widget = if draft.to.is_empty() {
+            SelectedSendWidget::To
+        } else {
+            SelectedSendWidget::Text
+        };
+
+        self.send_to = draft.to;
+        self.send_cc = draft.cc;
+        self.send_bcc = String::default();
+        self.send_subject = draft.subject;
+        self.send_text = draft.text;
+        self.send_in_reply_to = draft.in_reply_to;
+        self.send_references = draft.references;
+        self.show_send_status = false;
+
+        self.select_send_widget();
+    }
+
+    fn send_email(&mut self) {
+        if self.is_awaiting_send || !self.is_send_form_valid() {
+            return;
+        }
+
         let Some(selected_account_idx) = self.accounts_list_selected else {
             return;
         };
@@ -717,12 +1422,43 @@ impl App {
             return;
         };
 
-        let _ = actions_tx.send(Actions::SendMessage {
+        let (Ok(to), Ok(cc), Ok(bcc)) = (
+            draft::parse_recipients(&self.send_to),
+            draft::parse_recipients(&self.send_cc),
+            draft::parse_recipients(&self.send_bcc),
+        ) else {
+            return;
+        };
+
+        self.should_send = Some(Actions::SendMessage {
             login,
-            to: self.send_to.clone(),
+            to,
+            cc,
+            bcc,
             subject: self.send_subject.clone(),
             text: self.send_text.clone(),
+            in_reply_to: self.send_in_reply_to.clone(),
+            references: self.send_references.clone(),
         });
+
+        self.is_awaiting_send = true;
+        self.show_send_status = true;
+    }
+
+    // Fields are only cleared once the message is sent, so a failed one can be retried
+    fn handle_send_status(&mut self) {
+        if !self.is_awaiting_send {
+            return;
+        }
+
+        match self.view_state.send_status {
+            Some(SendStatus::Sent) => {
+                self.clear_send_form();
+                self.is_awaiting_send = false;
+            }
+            Some(SendStatus::Failed(_)) => self.is_awaiting_send = false,
+            _ => {}
+        }
     }
 
     fn ads_remove() {
@@ -1,27 +1,53 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use crate::state::State;
+use crate::{
+    cache::Cache,
+    config::{AccountConfig, Config, PasswordSource, ServerConfig},
+    query,
+    state::State,
+};
 
 use clap::Parser;
 
 use anyhow::Result;
-use secret::Secret;
 
 #[derive(Parser)]
 pub struct Args {
     #[clap(alias = "account", short, long, value_parser, num_args = 1..)]
     pub accounts: Vec<String>,
+
+    /// Path to the config file, defaults to ~/.config/tldr-email/config.toml
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Manual IMAP server: login,host=imap.inbox.com,port=993[,encryption=tls][,login=user]
+    #[clap(long, value_parser, num_args = 1..)]
+    pub imap: Vec<String>,
+
+    /// Manual SMTP server: login,host=smtp.inbox.com,port=465[,encryption=tls][,login=user]
+    #[clap(long, value_parser, num_args = 1..)]
+    pub smtp: Vec<String>,
 }
 
 pub async fn get_initial_state_from_args() -> Result<Arc<State>> {
-    let state = Arc::new(State::default());
-
     let args = Args::parse();
 
-    if args.accounts.is_empty() {
-        anyhow::bail!("provide at least one --account login:password");
-    }
+    // Mail is still browsable without the cache, just not offline
+    let cache = Cache::open()
+        .inspect_err(|err| eprintln!("Cannot open the message cache: {:?}", err))
+        .ok();
+
+    let config = Config::load(args.config.as_deref())?;
+
+    let state = Arc::new(State::new(
+        cache,
+        config.page_size,
+        query::saved_queries_path(args.config.as_deref()),
+    ));
+
+    let mut accounts = config.accounts;
 
+    // CLI accounts override config accounts with the same login
     for account_str in args.accounts {
         let parts: Vec<&str> = account_str.split(':').collect();
 
@@ -29,9 +55,73 @@ pub async fn get_initial_state_from_args() -> Result<Arc<State>> {
             anyhow::bail!("invalid account format. Expected 'login:password'");
         }
 
-        state
-            .add_account(parts[0].to_string(), Secret::new_raw(parts[1].to_string()))
-            .await;
+        let login = parts[0].to_string();
+        let password = PasswordSource::Raw(parts[1].to_string());
+
+        if let Some(account) = accounts.iter_mut().find(|a| a.login == login) {
+            account.password = Some(password);
+        } else {
+            accounts.push(AccountConfig {
+                login,
+                display_name: None,
+                password: Some(password),
+                oauth2: None,
+                imap: None,
+                smtp: None,
+            });
+        }
+    }
+
+    for imap_str in args.imap {
+        let (login, server) = ServerConfig::parse_cli(&imap_str)?;
+
+        let Some(account) = accounts.iter_mut().find(|a| a.login == login) else {
+            anyhow::bail!("--imap given for unknown account {}", login);
+        };
+
+        account.imap = Some(server);
+    }
+
+    for smtp_str in args.smtp {
+        let (login, server) = ServerConfig::parse_cli(&smtp_str)?;
+
+        let Some(account) = accounts.iter_mut().find(|a| a.login == login) else {
+            anyhow::bail!("--smtp given for unknown account {}", login);
+        };
+
+        account.smtp = Some(server);
+    }
+
+    if accounts.is_empty() {
+        anyhow::bail!(
+            "provide at least one --account login:password or an account in the config file"
+        );
+    }
+
+    for account in accounts {
+        if account.password.is_none() && account.oauth2.is_none() {
+            anyhow::bail!(
+                "provide a password or oauth2 settings for {}",
+                account.login
+            );
+        }
+
+        state.add_account(account).await;
+    }
+
+    // Queries that no longer parse would silently list the whole folder
+    let saved_queries = match &state.saved_queries_path {
+        Some(path) => query::load_saved_queries(path)?,
+        None => Vec::new(),
+    };
+
+    for saved in saved_queries {
+        if let Err(err) = query::parse(&saved.query) {
+            eprintln!("Skipping saved query {}: {:#}", saved.name, err);
+            continue;
+        }
+
+        state.saved_queries.write().await.push(saved);
     }
 
     Ok(state)
@@ -0,0 +1,461 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use email::envelope::{Address, Envelope, Flag, Flags};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::query::Query;
+
+// Bumped whenever a table changes, the cache is then rebuilt from the server
+const SCHEMA_VERSION: i32 = 2;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS folders (
+        login TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (login, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS envelopes (
+        login TEXT NOT NULL,
+        folder TEXT NOT NULL,
+        id TEXT NOT NULL,
+        message_id TEXT NOT NULL,
+        in_reply_to TEXT,
+        flags TEXT NOT NULL,
+        from_name TEXT,
+        from_addr TEXT NOT NULL,
+        to_name TEXT,
+        to_addr TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        date TEXT NOT NULL,
+        has_attachment INTEGER NOT NULL,
+        PRIMARY KEY (login, folder, id)
+    );
+
+    CREATE TABLE IF NOT EXISTS pages (
+        login TEXT NOT NULL,
+        folder TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        page_size INTEGER NOT NULL,
+        uid_validity INTEGER NOT NULL,
+        uid_next INTEGER NOT NULL,
+        exists_count INTEGER NOT NULL,
+        ids TEXT NOT NULL,
+        PRIMARY KEY (login, folder, page, page_size)
+    );
+
+    CREATE TABLE IF NOT EXISTS bodies (
+        login TEXT NOT NULL,
+        folder TEXT NOT NULL,
+        id TEXT NOT NULL,
+        text TEXT NOT NULL,
+        PRIMARY KEY (login, folder, id)
+    );
+";
+
+/// What the server reported when a folder was examined. Cached pages fetched
+/// with a different status are outdated, a different UIDVALIDITY means every
+/// cached UID of the folder is meaningless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FolderStatus {
+    pub uid_validity: u32,
+    pub uid_next: u32,
+    pub exists: u32,
+}
+
+/// Folders, envelope pages and message bodies of every account, kept in
+/// ~/.cache/tldr-email so mail can be browsed before connecting or offline
+#[derive(Debug)]
+pub struct Cache {
+    conn: Mutex<Connection>,
+}
+
+pub fn default_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache| cache.join("tldr-email").join("cache.sqlite3"))
+}
+
+impl Cache {
+    pub fn open() -> Result<Self> {
+        let Some(path) = default_cache_path() else {
+            anyhow::bail!("cannot find the cache directory");
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create cache directory {}", dir.display()))?;
+        }
+
+        let conn = Connection::open(&path)
+            .with_context(|| format!("cannot open cache {}", path.display()))?;
+
+        let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        if version != SCHEMA_VERSION {
+            conn.execute_batch(
+                "DROP TABLE IF EXISTS folders;
+                 DROP TABLE IF EXISTS envelopes;
+                 DROP TABLE IF EXISTS pages;
+                 DROP TABLE IF EXISTS bodies;",
+            )
+            .context("cannot clear outdated cache tables")?;
+        }
+
+        conn.execute_batch(SCHEMA)
+            .context("cannot create cache tables")?;
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn folders(&self, login: &str) -> Result<Option<Vec<String>>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt =
+            conn.prepare("SELECT name FROM folders WHERE login = ?1 ORDER BY position")?;
+
+        let folders = stmt
+            .query_map(params![login], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        Ok(Some(folders).filter(|f| !f.is_empty()))
+    }
+
+    pub fn store_folders(&self, login: &str, folders: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM folders WHERE login = ?1", params![login])?;
+
+        for (position, name) in folders.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO folders (login, position, name) VALUES (?1, ?2, ?3)",
+                params![login, position, name],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Status of the most recently cached page of a folder
+    pub fn folder_status(&self, login: &str, folder: &str) -> Result<Option<FolderStatus>> {
+        let conn = self.conn.lock().unwrap();
+
+        let status = conn
+            .query_row(
+                "SELECT uid_validity, uid_next, exists_count FROM pages
+                 WHERE login = ?1 AND folder = ?2 LIMIT 1",
+                params![login, folder],
+                |row| {
+                    Ok(FolderStatus {
+                        uid_validity: row.get(0)?,
+                        uid_next: row.get(1)?,
+                        exists: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(status)
+    }
+
+    pub fn clear_folder(&self, login: &str, folder: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for table in ["envelopes", "pages", "bodies"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE login = ?1 AND folder = ?2", table),
+                params![login, folder],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns the cached page along with the folder status it was fetched at
+    pub fn page(
+        &self,
+        login: &str,
+        folder: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<Option<(FolderStatus, Vec<Envelope>)>> {
+        let conn = self.conn.lock().unwrap();
+
+        let cached = conn
+            .query_row(
+                "SELECT uid_validity, uid_next, exists_count, ids FROM pages
+                 WHERE login = ?1 AND folder = ?2 AND page = ?3 AND page_size = ?4",
+                params![login, folder, page, page_size],
+                |row| {
+                    let status = FolderStatus {
+                        uid_validity: row.get(0)?,
+                        uid_next: row.get(1)?,
+                        exists: row.get(2)?,
+                    };
+
+                    Ok((status, row.get::<_, String>(3)?))
+                },
+            )
+            .optional()?;
+
+        let Some((status, ids)) = cached else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT id, message_id, in_reply_to, flags, from_name, from_addr,
+                    to_name, to_addr, subject, date, has_attachment
+             FROM envelopes WHERE login = ?1 AND folder = ?2 AND id = ?3",
+        )?;
+
+        let mut envelopes = Vec::new();
+
+        for id in ids.split_whitespace() {
+            let Some(envelope) = stmt
+                .query_row(params![login, folder, id], envelope_from_row)
+                .optional()?
+            else {
+                // A page with missing envelopes cannot be shown as it was
+                return Ok(None);
+            };
+
+            envelopes.push(envelope);
+        }
+
+        Ok(Some((status, envelopes)))
+    }
+
+    pub fn store_page(
+        &self,
+        login: &str,
+        folder: &str,
+        page: usize,
+        page_size: usize,
+        status: FolderStatus,
+        envelopes: &[Envelope],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for envelope in envelopes {
+            tx.execute(
+                "INSERT OR REPLACE INTO envelopes (
+                    login, folder, id, message_id, in_reply_to, flags, from_name,
+                    from_addr, to_name, to_addr, subject, date, has_attachment
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    login,
+                    folder,
+                    envelope.id,
+                    envelope.message_id,
+                    envelope.in_reply_to,
+                    flags_to_string(&envelope.flags),
+                    envelope.from.name,
+                    envelope.from.addr,
+                    envelope.to.name,
+                    envelope.to.addr,
+                    envelope.subject,
+                    envelope.date.to_rfc3339(),
+                    envelope.has_attachment,
+                ],
+            )?;
+        }
+
+        let ids = envelopes
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        tx.execute(
+            "INSERT OR REPLACE INTO pages (
+                login, folder, page, page_size, uid_validity, uid_next, exists_count, ids
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                login,
+                folder,
+                page,
+                page_size,
+                status.uid_validity,
+                status.uid_next,
+                status.exists,
+                ids
+            ],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Searches every cached envelope of the folder, with its body when it was opened
+    pub fn search(
+        &self,
+        login: &str,
+        folder: &str,
+        query: &Query,
+        page: usize,
+        page_size: usize,
+    ) -> Result<Vec<Envelope>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT e.id, e.message_id, e.in_reply_to, e.flags, e.from_name, e.from_addr,
+                    e.to_name, e.to_addr, e.subject, e.date, e.has_attachment, b.text
+             FROM envelopes e
+             LEFT JOIN bodies b ON b.login = e.login AND b.folder = e.folder AND b.id = e.id
+             WHERE e.login = ?1 AND e.folder = ?2",
+        )?;
+
+        let envelopes = stmt
+            .query_map(params![login, folder], |row| {
+                Ok((envelope_from_row(row)?, row.get::<_, Option<String>>(11)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|(envelope, body)| query.matches(envelope, body.as_deref()))
+            .map(|(envelope, _)| envelope)
+            .collect::<Vec<_>>();
+
+        Ok(query
+            .sort(envelopes)
+            .into_iter()
+            .skip(page * page_size)
+            .take(page_size)
+            .collect())
+    }
+
+    pub fn set_flag(
+        &self,
+        login: &str,
+        folder: &str,
+        ids: &[String],
+        flag: &Flag,
+        is_set: bool,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for id in ids {
+            let flags = tx
+                .query_row(
+                    "SELECT flags FROM envelopes WHERE login = ?1 AND folder = ?2 AND id = ?3",
+                    params![login, folder, id],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
+
+            let Some(flags) = flags else {
+                continue;
+            };
+
+            let mut flags = Flags::from(flags);
+
+            if is_set {
+                flags.insert(flag.clone());
+            } else {
+                flags.remove(flag);
+            }
+
+            tx.execute(
+                "UPDATE envelopes SET flags = ?4 WHERE login = ?1 AND folder = ?2 AND id = ?3",
+                params![login, folder, id, flags_to_string(&flags)],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    // Pages listing the envelopes are outdated by the new folder status anyway
+    pub fn remove_envelopes(&self, login: &str, folder: &str, ids: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for id in ids {
+            for table in ["envelopes", "bodies"] {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE login = ?1 AND folder = ?2 AND id = ?3",
+                        table
+                    ),
+                    params![login, folder, id],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn body(&self, login: &str, folder: &str, id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+
+        let body = conn
+            .query_row(
+                "SELECT text FROM bodies WHERE login = ?1 AND folder = ?2 AND id = ?3",
+                params![login, folder, id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(body)
+    }
+
+    pub fn store_body(&self, login: &str, folder: &str, id: &str, text: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO bodies (login, folder, id, text) VALUES (?1, ?2, ?3, ?4)",
+            params![login, folder, id, text],
+        )?;
+
+        Ok(())
+    }
+}
+
+// Flags are stored space separated, the way email-lib parses them back
+fn flags_to_string(flags: &Flags) -> String {
+    flags
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn envelope_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Envelope> {
+    let date: String = row.get(9)?;
+
+    let date = DateTime::parse_from_rfc3339(&date).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(9, rusqlite::types::Type::Text, Box::new(err))
+    })?;
+
+    Ok(Envelope {
+        id: row.get(0)?,
+        message_id: row.get(1)?,
+        in_reply_to: row.get(2)?,
+        flags: Flags::from(row.get::<_, String>(3)?),
+        from: Address {
+            name: row.get(4)?,
+            addr: row.get(5)?,
+        },
+        to: Address {
+            name: row.get(6)?,
+            addr: row.get(7)?,
+        },
+        subject: row.get(8)?,
+        date,
+        has_attachment: row.get(10)?,
+    })
+}
@@ -0,0 +1,235 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use secret::Secret;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Rows loaded at once in the messages table, fits its height by default
+    pub page_size: Option<usize>,
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    pub login: String,
+    pub display_name: Option<String>,
+    pub password: Option<PasswordSource>,
+    pub oauth2: Option<OAuth2Config>,
+    pub imap: Option<ServerConfig>,
+    pub smtp: Option<ServerConfig>,
+}
+
+/// Where the account password comes from:
+///
+/// ```toml
+/// password = "raw password"
+/// password = { command = "pass show mail@inbox.com" }
+/// password = { keyring = "mail@inbox.com" }
+/// password = { env = "MAIL_PASSWORD" }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PasswordSource {
+    Raw(String),
+    Command { command: String },
+    Keyring { keyring: String },
+    Env { env: String },
+}
+
+impl PasswordSource {
+    // Command and keyring secrets are only resolved by email-lib on authentication
+    pub fn to_secret(&self) -> Result<Secret> {
+        match self {
+            Self::Raw(password) => Ok(Secret::new_raw(password)),
+            Self::Command { command } => Ok(Secret::new_command(command)),
+            Self::Keyring { keyring } => Secret::try_new_keyring_entry(keyring.clone())
+                .with_context(|| format!("cannot open keyring entry {}", keyring)),
+            Self::Env { env } => std::env::var(env)
+                .map(Secret::new_raw)
+                .with_context(|| format!("cannot read password from ${}", env)),
+        }
+    }
+}
+
+/// OAuth 2.0 authorization code flow settings, used instead of the password.
+/// Access and refresh tokens are kept in the keyring.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuth2Config {
+    /// Fills in the endpoints and scopes below
+    pub provider: Option<OAuth2Provider>,
+    pub client_id: String,
+    pub client_secret: Option<PasswordSource>,
+    pub auth_url: Option<String>,
+    pub token_url: Option<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub pkce: bool,
+    /// Port of the local redirect listener, the first free one by default
+    pub redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OAuth2Provider {
+    Gmail,
+    Outlook,
+}
+
+impl OAuth2Config {
+    pub fn auth_url(&self) -> Result<String> {
+        match (&self.auth_url, self.provider) {
+            (Some(auth_url), _) => Ok(auth_url.clone()),
+            (None, Some(OAuth2Provider::Gmail)) => {
+                Ok("https://accounts.google.com/o/oauth2/v2/auth".to_string())
+            }
+            (None, Some(OAuth2Provider::Outlook)) => {
+                Ok("https://login.microsoftonline.com/common/oauth2/v2.0/authorize".to_string())
+            }
+            (None, None) => bail!("missing oauth2 auth_url"),
+        }
+    }
+
+    pub fn token_url(&self) -> Result<String> {
+        match (&self.token_url, self.provider) {
+            (Some(token_url), _) => Ok(token_url.clone()),
+            (None, Some(OAuth2Provider::Gmail)) => {
+                Ok("https://www.googleapis.com/oauth2/v3/token".to_string())
+            }
+            (None, Some(OAuth2Provider::Outlook)) => {
+                Ok("https://login.microsoftonline.com/common/oauth2/v2.0/token".to_string())
+            }
+            (None, None) => bail!("missing oauth2 token_url"),
+        }
+    }
+
+    pub fn scopes(&self) -> Vec<String> {
+        if !self.scopes.is_empty() {
+            return self.scopes.clone();
+        }
+
+        match self.provider {
+            Some(OAuth2Provider::Gmail) => vec!["https://mail.google.com/".to_string()],
+            Some(OAuth2Provider::Outlook) => vec![
+                "https://outlook.office.com/IMAP.AccessAsUser.All".to_string(),
+                "https://outlook.office.com/SMTP.Send".to_string(),
+                "offline_access".to_string(),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Manually configured server, autoconfig is skipped when it is given
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    /// Defaults to the account login
+    pub login: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncryptionConfig {
+    #[default]
+    Tls,
+    StartTls,
+    None,
+}
+
+impl FromStr for EncryptionConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tls" => Ok(Self::Tls),
+            "start-tls" => Ok(Self::StartTls),
+            "none" => Ok(Self::None),
+            _ => bail!(
+                "invalid encryption {}. Expected 'tls', 'start-tls' or 'none'",
+                s
+            ),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses `login,host=imap.inbox.com,port=993[,encryption=tls][,login=user]`
+    pub fn parse_cli(value: &str) -> Result<(String, Self)> {
+        let mut parts = value.split(',');
+
+        let Some(account_login) = parts.next().filter(|l| !l.is_empty()) else {
+            bail!("invalid server format. Expected 'login,host=...,port=...'");
+        };
+
+        let (mut host, mut port, mut encryption, mut login) = (None, None, None, None);
+
+        for part in parts {
+            let Some((key, value)) = part.split_once('=') else {
+                bail!("invalid server option {}. Expected 'key=value'", part);
+            };
+
+            match key {
+                "host" => host = Some(value.to_string()),
+                "port" => port = Some(value.parse().context("invalid server port")?),
+                "encryption" => encryption = Some(value.parse()?),
+                "login" => login = Some(value.to_string()),
+                _ => bail!("unknown server option {}", key),
+            }
+        }
+
+        let Some(host) = host else {
+            bail!("missing server host for {}", account_login);
+        };
+
+        let Some(port) = port else {
+            bail!("missing server port for {}", account_login);
+        };
+
+        Ok((
+            account_login.to_string(),
+            Self {
+                host,
+                port,
+                encryption: encryption.unwrap_or_default(),
+                login,
+            },
+        ))
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("tldr-email").join("config.toml"))
+}
+
+impl Config {
+    // An explicitly given path must exist, the default one is optional
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("cannot read config file {}", path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("cannot parse config file {}", path.display()))
+    }
+}
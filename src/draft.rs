@@ -0,0 +1,379 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use email_address::EmailAddress;
+use mail_parser::{Addr, Message};
+
+/// How the open message is answered from the Message window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyKind {
+    Reply,
+    ReplyAll,
+    Forward,
+}
+
+/// One recipient, `Name <addr@inbox.com>` or a bare `addr@inbox.com`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub addr: String,
+}
+
+impl fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(name) = &self.name else {
+            return write!(f, "{}", self.addr);
+        };
+
+        // Names with specials have to be quoted to keep the list parsable
+        if name.contains(|c: char| "()<>[]:;@\\,.\"".contains(c)) {
+            write!(
+                f,
+                "\"{}\" <{}>",
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                self.addr
+            )
+        } else {
+            write!(f, "{} <{}>", name, self.addr)
+        }
+    }
+}
+
+/// A compose form filled from another message
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    pub to: String,
+    pub cc: String,
+    pub subject: String,
+    pub text: String,
+    /// Threads the answer under the original, empty for a forward
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+}
+
+pub fn answer(message: &Message<'_>, kind: ReplyKind, login: &str) -> Draft {
+    // Encoded words can decode to line breaks
+    let subject = single_line(message.subject().unwrap_or_default());
+    let subject = subject.as_str();
+    let body = message.body_text(0).unwrap_or_default();
+
+    let from = message
+        .from()
+        .and_then(|from| from.first())
+        .map(format_addr)
+        .unwrap_or_default();
+
+    let date = message.date().map(|d| d.to_rfc822()).unwrap_or_default();
+
+    if kind == ReplyKind::Forward {
+        let to = message
+            .to()
+            .map(|to| to.iter().map(format_addr).collect::<Vec<_>>().join(", "))
+            .unwrap_or_default();
+
+        return Draft {
+            subject: prefixed("Fwd:", subject),
+            text: format!(
+                "\n\n---------- Forwarded message ----------\nFrom: {}\nDate: {}\nSubject: {}\nTo: {}\n\n{}",
+                from,
+                date,
+                subject,
+                to,
+                body.lines().collect::<Vec<_>>().join("\n")
+            ),
+            ..Default::default()
+        };
+    }
+
+    // Answers go where the sender asked them to
+    let reply_to = message
+        .reply_to()
+        .or(message.from())
+        .and_then(|addrs| addrs.first());
+
+    let to_addr = reply_to.and_then(|addr| addr.address()).unwrap_or_default();
+    let mut cc: Vec<&Addr<'_>> = Vec::new();
+
+    if kind == ReplyKind::ReplyAll {
+        let others = message
+            .to()
+            .into_iter()
+            .chain(message.cc())
+            .flat_map(|addrs| addrs.iter());
+
+        for other in others {
+            let Some(addr) = other.address() else {
+                continue;
+            };
+
+            let is_known = addr.eq_ignore_ascii_case(login)
+                || addr.eq_ignore_ascii_case(to_addr)
+                || cc
+                    .iter()
+                    .any(|c| c.address().is_some_and(|c| c.eq_ignore_ascii_case(addr)));
+
+            if !is_known {
+                cc.push(other);
+            }
+        }
+    }
+
+    let quoted = body
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut references: Vec<String> = message
+        .references()
+        .as_text_list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+    references.extend(message.message_id().map(str::to_string));
+
+    Draft {
+        to: reply_to.map(format_addr).unwrap_or_default(),
+        cc: cc
+            .into_iter()
+            .map(format_addr)
+            .collect::<Vec<_>>()
+            .join(", "),
+        subject: prefixed("Re:", subject),
+        text: format!("\n\nOn {}, {} wrote:\n{}", date, from, quoted),
+        in_reply_to: message.message_id().map(str::to_string),
+        references,
+    }
+}
+
+/// Splits a recipient list on the commas outside of quotes and angle brackets.
+/// Entries keep their spacing, so joining them with commas gives the text back.
+pub fn split_recipients(text: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+    let mut is_bracketed = false;
+
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => is_quoted = !is_quoted,
+            '<' if !is_quoted => is_bracketed = true,
+            '>' if !is_quoted => is_bracketed = false,
+            ',' if !is_quoted && !is_bracketed => {
+                entries.push(&text[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    entries.push(&text[start..]);
+
+    entries
+}
+
+pub fn parse_mailbox(entry: &str) -> Option<Mailbox> {
+    let entry = entry.trim();
+
+    let mailbox = match entry.strip_suffix('>').and_then(|e| e.rsplit_once('<')) {
+        Some((name, addr)) => {
+            let name = name.trim();
+            let name = name
+                .strip_prefix('"')
+                .and_then(|n| n.strip_suffix('"'))
+                .map(|n| n.replace("\\\"", "\"").replace("\\\\", "\\"))
+                .unwrap_or_else(|| name.to_string());
+
+            Mailbox {
+                name: Some(name).filter(|n| !n.is_empty()),
+                addr: addr.trim().to_string(),
+            }
+        }
+        None => Mailbox {
+            name: None,
+            addr: entry.to_string(),
+        },
+    };
+
+    // Line breaks in a name would end the header early
+    let is_valid = EmailAddress::is_valid(&mailbox.addr)
+        && !mailbox
+            .name
+            .as_ref()
+            .is_some_and(|n| n.contains(['\r', '\n']));
+
+    Some(mailbox).filter(|_| is_valid)
+}
+
+/// Parses a comma separated recipient list, empty entries are skipped
+pub fn parse_recipients(text: &str) -> Result<Vec<Mailbox>> {
+    let mut mailboxes = Vec::new();
+
+    for entry in split_recipients(text) {
+        if entry.trim().is_empty() {
+            continue;
+        }
+
+        let Some(mailbox) = parse_mailbox(entry) else {
+            bail!("invalid address {}", entry.trim());
+        };
+
+        mailboxes.push(mailbox);
+    }
+
+    Ok(mailboxes)
+}
+
+/// Replaces line breaks and other control characters, which would end a
+/// header early and let the rest of it pass for headers of its own
+pub fn single_line(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+// Answering an answer keeps a single prefix
+fn prefixed(prefix: &str, subject: &str) -> String {
+    let is_prefixed = subject
+        .get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix));
+
+    if is_prefixed {
+        subject.to_string()
+    } else {
+        format!("{} {}", prefix, subject)
+    }
+}
+
+fn format_addr(addr: &Addr<'_>) -> String {
+    match (addr.name(), addr.address()) {
+        (name, Some(address)) => Mailbox {
+            name: name.map(str::to_string),
+            addr: address.to_string(),
+        }
+        .to_string(),
+        (Some(name), None) => name.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::*;
+
+    fn mailbox(name: Option<&str>, addr: &str) -> Mailbox {
+        Mailbox {
+            name: name.map(str::to_string),
+            addr: addr.to_string(),
+        }
+    }
+
+    #[test]
+    fn quoted_names_keep_their_commas() {
+        assert_eq!(
+            parse_recipients(r#""Doe, Jane" <jane@inbox.com>, bob@inbox.com"#).unwrap(),
+            vec![
+                mailbox(Some("Doe, Jane"), "jane@inbox.com"),
+                mailbox(None, "bob@inbox.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_are_unescaped_and_written_back() {
+        let parsed = parse_recipients(r#""Jane \"JD\" Doe" <jane@inbox.com>"#).unwrap();
+
+        assert_eq!(
+            parsed,
+            vec![mailbox(Some(r#"Jane "JD" Doe"#), "jane@inbox.com")]
+        );
+        assert_eq!(
+            parsed[0].to_string(),
+            r#""Jane \"JD\" Doe" <jane@inbox.com>"#
+        );
+    }
+
+    #[test]
+    fn bare_addresses_and_empty_entries() {
+        assert_eq!(
+            parse_recipients(" jane@inbox.com ,, ").unwrap(),
+            vec![mailbox(None, "jane@inbox.com")]
+        );
+        assert_eq!(
+            parse_recipients("Jane <jane@inbox.com>").unwrap(),
+            vec![mailbox(Some("Jane"), "jane@inbox.com")]
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_errors() {
+        assert!(parse_recipients("jane@inbox.com, not an address").is_err());
+        assert!(parse_recipients("Jane <jane>").is_err());
+    }
+
+    #[test]
+    fn line_breaks_cannot_inject_headers() {
+        assert!(parse_recipients("jane@inbox.com\r\nBcc: eve@inbox.com").is_err());
+        assert!(parse_recipients("\"Jane\r\nBcc: eve@inbox.com\" <jane@inbox.com>").is_err());
+    }
+
+    #[test]
+    fn line_breaks_cannot_inject_headers_through_the_subject() {
+        assert_eq!(single_line("Hi\r\nX-Injected: yes"), "Hi  X-Injected: yes");
+
+        let message = MessageParser::default()
+            .parse(
+                "From: alice@inbox.com\r\n\
+                Subject: =?utf-8?Q?Hi=0D=0AX-Injected:_yes?=\r\n\
+                \r\n\
+                Hi\r\n",
+            )
+            .unwrap();
+
+        for kind in [ReplyKind::Reply, ReplyKind::Forward] {
+            let subject = answer(&message, kind, "me@inbox.com").subject;
+
+            assert!(!subject.contains(['\r', '\n']), "{:?}", subject);
+        }
+    }
+
+    const MESSAGE: &str = "From: Alice <alice@inbox.com>\r\n\
+        To: me@inbox.com, Bob <bob@inbox.com>\r\n\
+        Cc: BOB@inbox.com, carol@inbox.com, Me <ME@inbox.com>, alice@inbox.com\r\n\
+        Subject: Re: Lunch\r\n\
+        Message-ID: <lunch-2@inbox.com>\r\n\
+        References: <lunch-1@inbox.com>\r\n\
+        \r\n\
+        Noon?\r\n";
+
+    #[test]
+    fn reply_all_drops_the_own_address_and_duplicates() {
+        let message = MessageParser::default().parse(MESSAGE).unwrap();
+        let draft = answer(&message, ReplyKind::ReplyAll, "me@inbox.com");
+
+        assert_eq!(draft.to, "Alice <alice@inbox.com>");
+        assert_eq!(draft.cc, "Bob <bob@inbox.com>, carol@inbox.com");
+        assert_eq!(draft.in_reply_to.as_deref(), Some("lunch-2@inbox.com"));
+        assert_eq!(draft.references, ["lunch-1@inbox.com", "lunch-2@inbox.com"]);
+    }
+
+    #[test]
+    fn prefixes_are_not_doubled() {
+        let message = MessageParser::default().parse(MESSAGE).unwrap();
+
+        assert_eq!(
+            answer(&message, ReplyKind::Reply, "me@inbox.com").subject,
+            "Re: Lunch"
+        );
+        assert_eq!(
+            answer(&message, ReplyKind::Forward, "me@inbox.com").subject,
+            "Fwd: Re: Lunch"
+        );
+        assert_eq!(prefixed("Fwd:", "FWD: Lunch"), "FWD: Lunch");
+        assert_eq!(prefixed("Re:", "Lunch"), "Re: Lunch");
+    }
+}
@@ -1,35 +1,43 @@
 use email::{
-    account::config::passwd::PasswordConfig,
+    account::config::{
+        oauth2::{OAuth2Config, OAuth2Method, OAuth2Scopes},
+        passwd::PasswordConfig,
+    },
     autoconfig::{
         config::{AutoConfig, SecurityType, ServerType},
         from_addr,
     },
     backend::Backend,
     envelope::{
+        flag::{add::AddFlags, remove::RemoveFlags},
         list::{ListEnvelopes, ListEnvelopesOptions},
-        Id,
+        Envelope, Flag, Id,
     },
-    folder::list::ListFolders,
+    folder::{expunge::ExpungeFolder, list::ListFolders},
     imap::ImapContext,
-    message::{get::GetMessages, send::SendMessageThenSaveCopy, Message},
-    smtp::{
-        config::{SmtpAuthConfig, SmtpConfig},
-        SmtpContextBuilder, SmtpContextSync,
-    },
-};
-use email_address::EmailAddress;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-    time::Duration,
+    message::{add::AddMessage, get::GetMessages, peek::PeekMessages, r#move::MoveMessages},
+    smtp::config::{SmtpAuthConfig, SmtpConfig},
 };
+use imap_types::sequence::SequenceSet;
+use mail_builder::{headers::address::Address, mime::make_boundary, MessageBuilder};
+use mail_send::{smtp::message::Message as SmtpMessage, SmtpClientBuilder};
+use secret::Secret;
+use std::{collections::HashMap, io, num::NonZeroU32, sync::Arc, time::Duration};
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use utf7_imap::encode_utf7_imap;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 
 use crate::{
-    actions::Actions,
-    state::{Account, State},
+    actions::{Actions, MoveTarget},
+    cache::FolderStatus,
+    config::{EncryptionConfig, ServerConfig},
+    draft::{self, Mailbox},
+    query::{Query, Sort},
+    queue::ActionQueue,
+    state::{
+        Account, AccountStatus, FolderEnvelopes, FolderSearch, OpenMessage, SendStatus, State,
+    },
 };
 use email::{
     account::config::AccountConfig,
@@ -38,91 +46,401 @@ use email::{
         config::{ImapAuthConfig, ImapConfig},
         ImapContextBuilder,
     },
-    tls::Encryption,
+    tls::{Encryption, Tls},
+    AnyBoxedError,
 };
 use futures::StreamExt;
 use tokio::{
-    sync::{
-        mpsc::{self, UnboundedReceiver, UnboundedSender},
-        RwLock,
-    },
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
     time::sleep,
 };
 
+enum Credentials {
+    Password(Secret),
+    OAuth2(Box<OAuth2Config>),
+}
+
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(300);
+
 pub struct EmailBackend {
-    account_to_backends_map: HashMap<String, (Backend<ImapContext>, Backend<SmtpContextSync>)>,
     tx: UnboundedSender<Actions>,
     rx: UnboundedReceiver<Actions>,
 }
 
 impl EmailBackend {
     pub async fn new(state: Arc<State>) -> Result<Self> {
-        let mut account_to_backends_map = HashMap::new();
-
+        // The one-time OAuth 2.0 authorization prints a link, so it has to
+        // happen before the TUI takes over the terminal
         for account in state.accounts.read().await.iter() {
-            println!("Loading autoconfig for {}", account.login);
-
-            let autoconfig = from_addr(account.login.clone()).await?;
-
-            let imap_backend = Self::build_imap(account, &autoconfig).await;
-            let smtp_backend = Self::build_smtp(account, &autoconfig).await;
-            account_to_backends_map.insert(account.login.clone(), (imap_backend?, smtp_backend?));
-
-            println!("{} – done", account.login);
+            if account.oauth2.is_some() {
+                if let Err(err) = Self::authorize(account).await {
+                    eprintln!(
+                        "OAuth 2.0 authorization failed for {}: {:?}",
+                        account.login, err
+                    );
+                }
+            }
         }
 
         let (tx, rx) = mpsc::unbounded_channel();
 
-        sleep(Duration::from_millis(400)).await;
-
-        Ok(Self {
-            account_to_backends_map,
-            tx,
-            rx,
-        })
+        Ok(Self { tx, rx })
     }
 
     // Will only be ran once due to consuming itself
     // TODO: maybe add an Once guard
     pub async fn spawn(self, state: Arc<State>) -> Result<UnboundedSender<Actions>> {
         let mut rx = UnboundedReceiverStream::new(self.rx);
-        let account_to_backends_map = self.account_to_backends_map;
         let tx = self.tx;
 
-        let debouncer = Arc::new(RwLock::new(HashSet::<Actions>::new()));
-        let debouncer_cloned = debouncer.clone();
+        let accounts = state.accounts.read().await.clone();
+
+        // Queues exist from the start, so actions wait for their account to connect
+        let queues: HashMap<(String, Lane), Arc<ActionQueue>> = accounts
+            .iter()
+            .flat_map(|a| [(a.login.clone(), Lane::Imap), (a.login.clone(), Lane::Smtp)])
+            .map(|key| (key, Arc::new(ActionQueue::default())))
+            .collect();
+
+        for account in accounts {
+            let imap_queue = queues[&(account.login.clone(), Lane::Imap)].clone();
+            let smtp_queue = queues[&(account.login.clone(), Lane::Smtp)].clone();
+
+            tokio::task::spawn(Self::connect(
+                account,
+                imap_queue,
+                smtp_queue,
+                tx.clone(),
+                state.clone(),
+            ));
+        }
 
         tokio::task::spawn(async move {
             while let Some(action) = rx.next().await {
-                debouncer.write().await.replace(action);
-                eprintln!("debouncer len = {}", debouncer.read().await.len());
-            }
-        });
+                let key = (action.login().to_string(), Lane::of(&action));
 
-        tokio::task::spawn(async move {
-            loop {
-                for action in debouncer_cloned.write().await.drain() {
-                    Self::execute_action(&account_to_backends_map, action, state.clone()).await;
+                let Some(queue) = queues.get(&key) else {
+                    Self::reject(&action, "Unknown account".to_string(), &state).await;
+                    continue;
+                };
+
+                state.track_request(&action).await;
+
+                // Cached data is shown right away, the worker then reconciles it with the server
+                let is_cached = Self::show_cached(&action, &state).await;
+
+                if let Some(AccountStatus::Failed(error)) =
+                    state.account_status(action.login()).await
+                {
+                    // Other actions wait for the account to come back, a send
+                    // would leave the compose form waiting for just as long
+                    if let Actions::SendMessage { .. } = action {
+                        Self::reject(&action, error, &state).await;
+                        continue;
+                    }
+
+                    // Browsing cached mail of an offline account is not an error
+                    if !is_cached && action.request().is_some() {
+                        state.report_error(action.login(), error).await;
+                    }
+
+                    state.set_updating(action.login(), false).await;
+                } else if action.request().is_some() {
+                    // Marked before it is queued, so the worker cannot have finished it already
+                    state.set_updating(action.login(), true).await;
                 }
 
-                // throttle actions
-                sleep(Duration::from_millis(500)).await;
+                // Flag changes show right away and are undone if the server rejects them
+                state.apply_flag(&action).await;
 
-                if debouncer_cloned.write().await.len() == 0 {
-                    *state.is_updating.write().await = false;
-                }
+                queue.push(action).await;
             }
         });
 
         Ok(tx)
     }
 
-    async fn build_imap(
+    // The compose form waits for a send result, so a dropped send has to fail it
+    async fn reject(action: &Actions, error: String, state: &State) {
+        if let Actions::SendMessage { .. } = action {
+            *state.send_status.write().await = Some(SendStatus::Failed(error.clone()));
+        }
+
+        state.report_error(action.login(), error).await;
+    }
+
+    async fn show_cached(action: &Actions, state: &State) -> bool {
+        let Some(cache) = &state.cache else {
+            return false;
+        };
+
+        match action {
+            Actions::ListFolders { login, .. } => {
+                let Some(folders) = cache.folders(login).ok().flatten() else {
+                    return false;
+                };
+
+                state
+                    .account_folders
+                    .write()
+                    .await
+                    .insert(login.clone(), Some(folders));
+            }
+
+            Actions::ListEnvelopes {
+                login,
+                folder,
+                page,
+                page_size,
+                query,
+                sort,
+                ..
+            } => {
+                let envelopes = match (query, sort) {
+                    (None, Sort::DateDesc) => cache
+                        .page(login, folder, *page, *page_size)
+                        .ok()
+                        .flatten()
+                        .map(|(_, envelopes)| envelopes),
+                    _ => {
+                        let listed = Query::sorted(query.clone(), *sort);
+                        cache.search(login, folder, &listed, *page, *page_size).ok()
+                    }
+                };
+
+                let Some(envelopes) = envelopes else {
+                    return false;
+                };
+
+                state
+                    .add_envelopes_page(
+                        login,
+                        FolderEnvelopes::page(
+                            folder.clone(),
+                            query.clone(),
+                            *sort,
+                            *page_size,
+                            *page,
+                            envelopes,
+                        ),
+                    )
+                    .await;
+            }
+
+            Actions::GetMessage {
+                login, folder, id, ..
+            } => {
+                let Some(text) = cache.body(login, folder, id).ok().flatten() else {
+                    return false;
+                };
+
+                state.account_messages.write().await.insert(
+                    login.clone(),
+                    Some(OpenMessage {
+                        folder: folder.clone(),
+                        id: id.clone(),
+                        text,
+                    }),
+                );
+            }
+
+            Actions::SendMessage { .. }
+            | Actions::SaveSentMessage { .. }
+            | Actions::PrepareDraft { .. }
+            | Actions::AddFlag { .. }
+            | Actions::RemoveFlag { .. }
+            | Actions::MoveMessages { .. }
+            | Actions::ExpungeMessages { .. } => return false,
+        }
+
+        state.notify_changed();
+
+        true
+    }
+
+    async fn connect(
+        account: Account,
+        imap_queue: Arc<ActionQueue>,
+        smtp_queue: Arc<ActionQueue>,
+        actions_tx: UnboundedSender<Actions>,
+        state: Arc<State>,
+    ) {
+        state
+            .set_account_status(&account.login, AccountStatus::Connecting)
+            .await;
+
+        let mut delay = RECONNECT_INITIAL_DELAY;
+
+        // An account offline at launch keeps being retried, its queued actions
+        // run once it is back
+        let (imap_server, imap_backend, smtp_server, smtp_backend) = loop {
+            match Self::build_backends(&account).await {
+                Ok(backends) => break backends,
+                Err(err) => {
+                    let error = format!("{:#}", err);
+
+                    for action in smtp_queue.drain().await {
+                        Self::reject(&action, error.clone(), &state).await;
+                    }
+
+                    let was_failed = matches!(
+                        state.account_status(&account.login).await,
+                        Some(AccountStatus::Failed(_))
+                    );
+
+                    state
+                        .set_account_status(&account.login, AccountStatus::Failed(error.clone()))
+                        .await;
+
+                    if !was_failed {
+                        state.report_error(&account.login, error).await;
+                    }
+
+                    state.set_updating(&account.login, false).await;
+
+                    sleep(delay).await;
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                }
+            }
+        };
+
+        tokio::task::spawn(
+            Worker {
+                account: account.clone(),
+                lane: Lane::Imap,
+                server: imap_server,
+                backend: LaneBackend::Imap(Box::new(imap_backend)),
+                queue: imap_queue,
+                actions_tx: actions_tx.clone(),
+            }
+            .run(state.clone()),
+        );
+
+        tokio::task::spawn(
+            Worker {
+                account: account.clone(),
+                lane: Lane::Smtp,
+                server: smtp_server,
+                backend: LaneBackend::Smtp(smtp_backend),
+                queue: smtp_queue,
+                actions_tx,
+            }
+            .run(state.clone()),
+        );
+
+        state
+            .set_account_status(&account.login, AccountStatus::Ready)
+            .await;
+        state.clear_error(&account.login).await;
+    }
+
+    async fn build_backends(
         account: &Account,
-        autoconfig: &AutoConfig,
-    ) -> Result<Backend<ImapContext>> {
-        let account_config = Arc::new(AccountConfig::default());
+    ) -> Result<(ServerConfig, Backend<ImapContext>, ServerConfig, SmtpSender)> {
+        let credentials = Self::credentials(account).await?;
+
+        // Autoconfig is only a fallback for servers that are not configured manually
+        let autoconfig = if account.imap.is_none() || account.smtp.is_none() {
+            Some(from_addr(account.login.clone()).await?)
+        } else {
+            None
+        };
+
+        let imap_server = match (&account.imap, &autoconfig) {
+            (Some(imap_server), _) => imap_server.clone(),
+            (None, Some(autoconfig)) => Self::imap_server_from_autoconfig(account, autoconfig)?,
+            (None, None) => unreachable!(),
+        };
+
+        let smtp_server = match (&account.smtp, &autoconfig) {
+            (Some(smtp_server), _) => smtp_server.clone(),
+            (None, Some(autoconfig)) => Self::smtp_server_from_autoconfig(account, autoconfig)?,
+            (None, None) => unreachable!(),
+        };
+
+        let (imap_backend, smtp_backend) = tokio::join!(
+            Self::build_imap(account, &credentials, &imap_server),
+            Self::build_smtp(account, &credentials, &smtp_server)
+        );
+
+        Ok((imap_server, imap_backend?, smtp_server, smtp_backend?))
+    }
 
+    // Runs the authorization code flow only when there is no access token yet
+    async fn authorize(account: &Account) -> Result<()> {
+        let Some(oauth2) = Self::oauth2_config(account)? else {
+            return Ok(());
+        };
+
+        oauth2
+            .configure(|| Err(io::Error::other("oauth2 client secret is not set")))
+            .await?;
+
+        Ok(())
+    }
+
+    // Also called from background reconnects, so it never asks the user to authorize
+    async fn credentials(account: &Account) -> Result<Credentials> {
+        let Some(oauth2) = Self::oauth2_config(account)? else {
+            let Some(password) = &account.password else {
+                bail!("No password or oauth2 settings for {}", account.login)
+            };
+
+            return Ok(Credentials::Password(password.to_secret()?));
+        };
+
+        if oauth2.access_token.find().await?.is_none() {
+            bail!(
+                "No OAuth 2.0 token for {}, restart the app to authorize it",
+                account.login
+            );
+        }
+
+        // Access tokens are short lived, so always start with a fresh one
+        if oauth2.refresh_token.find().await?.is_some() {
+            oauth2.refresh_access_token().await?;
+        }
+
+        Ok(Credentials::OAuth2(Box::new(oauth2)))
+    }
+
+    fn oauth2_config(account: &Account) -> Result<Option<OAuth2Config>> {
+        let Some(oauth2) = &account.oauth2 else {
+            return Ok(None);
+        };
+
+        Ok(Some(OAuth2Config {
+            method: OAuth2Method::XOAuth2,
+            client_id: oauth2.client_id.clone(),
+            client_secret: oauth2
+                .client_secret
+                .as_ref()
+                .map(|secret| secret.to_secret())
+                .transpose()?,
+            auth_url: oauth2.auth_url()?,
+            token_url: oauth2.token_url()?,
+            access_token: Secret::try_new_keyring_entry(format!(
+                "{}-oauth2-access-token",
+                account.login
+            ))?,
+            refresh_token: Secret::try_new_keyring_entry(format!(
+                "{}-oauth2-refresh-token",
+                account.login
+            ))?,
+            pkce: oauth2.pkce,
+            redirect_scheme: None,
+            redirect_host: None,
+            redirect_port: oauth2.redirect_port,
+            scopes: OAuth2Scopes::Scopes(oauth2.scopes()),
+        }))
+    }
+
+    fn imap_server_from_autoconfig(
+        account: &Account,
+        autoconfig: &AutoConfig,
+    ) -> Result<ServerConfig> {
         let imap_server = autoconfig
             .email_provider()
             .incoming_servers()
@@ -152,39 +470,18 @@ impl EmailBackend {
             bail!("No IMAP security type in autoconfig for {}", account.login)
         };
 
-        if !matches!(imap_encryption, SecurityType::Tls) {
-            bail!(
-                "IMAP server does not support Tls security in autoconfig for {}",
-                account.login
-            )
-        };
-
-        let imap_config = Arc::new(ImapConfig {
+        Ok(ServerConfig {
             host: imap_host.to_string(),
             port: *imap_port,
-            encryption: Some(Encryption::default()),
-            login: account.login.to_string(),
-            auth: ImapAuthConfig::Password(PasswordConfig(account.password.clone())),
-            ..Default::default()
-        });
-
-        let imap_ctx = ImapContextBuilder::new(account_config.clone(), imap_config.clone());
-
-        let imap = BackendBuilder::new(account_config, imap_ctx).build().await;
-
-        let Ok(imap) = imap else {
-            bail!("IMAP backend cannot be created for {}", account.login)
-        };
-
-        Ok(imap)
+            encryption: Self::encryption_from_autoconfig(imap_encryption),
+            login: None,
+        })
     }
 
-    async fn build_smtp(
+    fn smtp_server_from_autoconfig(
         account: &Account,
         autoconfig: &AutoConfig,
-    ) -> Result<Backend<SmtpContextSync>> {
-        let account_config = Arc::new(AccountConfig::default());
-
+    ) -> Result<ServerConfig> {
         let smtp_server = autoconfig
             .email_provider()
             .outgoing_servers()
@@ -214,159 +511,954 @@ impl EmailBackend {
             bail!("No SMTP security type in autoconfig for {}", account.login)
         };
 
-        if !matches!(smtp_encryption, SecurityType::Tls) {
-            bail!(
-                "SMTP server does not support Tls security in autoconfig for {}",
-                account.login
-            )
-        };
-
-        let smtp_config = Arc::new(SmtpConfig {
+        Ok(ServerConfig {
             host: smtp_host.to_string(),
             port: *smtp_port,
-            encryption: Some(Encryption::default()),
-            login: account.login.to_string(),
-            auth: SmtpAuthConfig::Password(PasswordConfig(account.password.clone())),
+            encryption: Self::encryption_from_autoconfig(smtp_encryption),
+            login: None,
+        })
+    }
+
+    fn encryption_from_autoconfig(security_type: &SecurityType) -> EncryptionConfig {
+        match security_type {
+            SecurityType::Tls => EncryptionConfig::Tls,
+            SecurityType::Starttls => EncryptionConfig::StartTls,
+            SecurityType::Plain => EncryptionConfig::None,
+        }
+    }
+
+    fn encryption(encryption: EncryptionConfig) -> Encryption {
+        match encryption {
+            EncryptionConfig::Tls => Encryption::Tls(Tls::default()),
+            EncryptionConfig::StartTls => Encryption::StartTls(Tls::default()),
+            EncryptionConfig::None => Encryption::None,
+        }
+    }
+
+    async fn build_imap(
+        account: &Account,
+        credentials: &Credentials,
+        imap_server: &ServerConfig,
+    ) -> Result<Backend<ImapContext>> {
+        let account_config = Arc::new(AccountConfig::default());
+
+        let imap_config = Arc::new(ImapConfig {
+            host: imap_server.host.clone(),
+            port: imap_server.port,
+            encryption: Some(Self::encryption(imap_server.encryption)),
+            login: imap_server
+                .login
+                .clone()
+                .unwrap_or_else(|| account.login.to_string()),
+            auth: match credentials {
+                Credentials::Password(password) => {
+                    ImapAuthConfig::Password(PasswordConfig(password.clone()))
+                }
+                Credentials::OAuth2(oauth2) => ImapAuthConfig::OAuth2(*oauth2.clone()),
+            },
+            ..Default::default()
         });
 
-        let smtp_ctx = SmtpContextBuilder::new(account_config.clone(), smtp_config.clone());
+        let imap_ctx = ImapContextBuilder::new(account_config.clone(), imap_config.clone());
 
-        let smtp = BackendBuilder::new(account_config, smtp_ctx).build().await;
+        let imap = BackendBuilder::new(account_config, imap_ctx).build().await;
 
-        let Ok(smtp) = smtp else {
-            bail!("SMTP backend cannot be created for {}", account.login)
+        let Ok(imap) = imap else {
+            bail!("IMAP backend cannot be created for {}", account.login)
         };
 
+        Ok(imap)
+    }
+
+    async fn build_smtp(
+        account: &Account,
+        credentials: &Credentials,
+        smtp_server: &ServerConfig,
+    ) -> Result<SmtpSender> {
+        let smtp_config = Arc::new(SmtpConfig {
+            host: smtp_server.host.clone(),
+            port: smtp_server.port,
+            encryption: Some(Self::encryption(smtp_server.encryption)),
+            login: smtp_server
+                .login
+                .clone()
+                .unwrap_or_else(|| account.login.to_string()),
+            auth: match credentials {
+                Credentials::Password(password) => {
+                    SmtpAuthConfig::Password(PasswordConfig(password.clone()))
+                }
+                Credentials::OAuth2(oauth2) => SmtpAuthConfig::OAuth2(*oauth2.clone()),
+            },
+        });
+
+        let smtp = SmtpSender {
+            config: smtp_config,
+        };
+
+        smtp.check()
+            .await
+            .with_context(|| format!("SMTP backend cannot be created for {}", account.login))?;
+
         Ok(smtp)
     }
 
+    async fn examine_folder(imap: &Backend<ImapContext>, folder: &str) -> Result<FolderStatus> {
+        let folder = imap.account_config.get_folder_alias(folder);
+
+        let data = imap
+            .context
+            .client()
+            .await
+            .examine_mailbox(encode_utf7_imap(folder.clone()))
+            .await
+            .with_context(|| format!("Cannot examine {}", folder))?;
+
+        Ok(FolderStatus {
+            uid_validity: data.uid_validity.map_or(0, NonZeroU32::get),
+            uid_next: data.uid_next.map_or(0, NonZeroU32::get),
+            exists: data.exists.unwrap_or_default(),
+        })
+    }
+
+    // Other clients may have changed the flags of a cached page, so its
+    // envelopes are fetched again by UID, which is far cheaper than listing
+    async fn refresh_envelopes(
+        imap: &Backend<ImapContext>,
+        folder: &str,
+        envelopes: Vec<Envelope>,
+    ) -> Result<Vec<Envelope>> {
+        if envelopes.is_empty() {
+            return Ok(envelopes);
+        }
+
+        let folder = imap.account_config.get_folder_alias(folder);
+
+        let uids: Vec<&str> = envelopes.iter().map(|e| e.id.as_str()).collect();
+        let uids = SequenceSet::try_from(uids.join(",").as_str())
+            .with_context(|| format!("Invalid UIDs in the cached page of {}", folder))?;
+
+        let mut client = imap.context.client().await;
+
+        client
+            .examine_mailbox(encode_utf7_imap(folder.clone()))
+            .await
+            .with_context(|| format!("Cannot examine {}", folder))?;
+
+        let fetched = client
+            .fetch_envelopes(uids)
+            .await
+            .with_context(|| format!("Cannot refresh envelopes of {}", folder))?;
+
+        // Fetches come back in any order, the page keeps the cached one
+        Ok(envelopes
+            .into_iter()
+            .map(|cached| {
+                fetched
+                    .iter()
+                    .find(|e| e.id == cached.id)
+                    .cloned()
+                    .unwrap_or(cached)
+            })
+            .collect())
+    }
+
+    // Special-use attributes win over the usual folder names
+    async fn target_folder(imap: &Backend<ImapContext>, target: MoveTarget) -> Result<String> {
+        let names: &[&str] = match &target {
+            MoveTarget::Folder(folder) => return Ok(folder.clone()),
+            MoveTarget::Trash => &["trash", "bin", "deleted items", "deleted messages"],
+            MoveTarget::Archive => &["archive", "archives", "all mail"],
+            MoveTarget::Sent => &["sent", "sent items", "sent mail", "sent messages"],
+        };
+
+        let folders = imap.list_folders().await.context("Cannot list folders")?;
+
+        let by_kind = folders.iter().find(|f| match (&target, &f.kind) {
+            (MoveTarget::Trash, Some(kind)) => kind.is_trash(),
+            (MoveTarget::Sent, Some(kind)) => kind.is_sent(),
+            _ => false,
+        });
+
+        let by_name = || {
+            folders.iter().find(|f| {
+                let leaf = f.name.rsplit(['/', '.']).next().unwrap_or(&f.name);
+                names.contains(&leaf.to_lowercase().as_str())
+            })
+        };
+
+        let Some(folder) = by_kind.or_else(by_name) else {
+            bail!("No {:?} folder found", target)
+        };
+
+        Ok(folder.name.clone())
+    }
+
     async fn execute_action(
-        account_map: &HashMap<String, (Backend<ImapContext>, Backend<SmtpContextSync>)>,
+        backend: &LaneBackend,
         action: Actions,
+        actions_tx: &UnboundedSender<Actions>,
         state: Arc<State>,
-    ) {
-        match action {
-            Actions::ListFolders { login } => {
+    ) -> Result<()> {
+        if !state.is_latest_request(&action).await {
+            return Ok(());
+        }
+
+        let request = action.clone();
+
+        match (action, backend) {
+            (Actions::ListFolders { login, .. }, LaneBackend::Imap(imap)) => {
+                let folders: Vec<String> = imap
+                    .list_folders()
+                    .await
+                    .context("Cannot list folders")?
+                    .iter()
+                    .map(|f| f.name.clone())
+                    .collect();
+
+                // The cache only saves round trips, failing to write it is not worth an error
+                if let Some(cache) = &state.cache {
+                    let _ = cache.store_folders(&login, &folders);
+                }
+
+                if !state.is_latest_request(&request).await {
+                    return Ok(());
+                }
+
                 state
                     .account_folders
                     .write()
                     .await
-                    .insert(login.clone(), None);
+                    .insert(login, Some(folders));
+            }
 
-                let Some(backends) = account_map.get(&login) else {
-                    return;
+            (
+                Actions::ListEnvelopes {
+                    login,
+                    folder,
+                    page,
+                    page_size,
+                    query: None,
+                    sort: Sort::DateDesc,
+                    ..
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                let status = Self::examine_folder(imap, &folder).await?;
+
+                let cached_page = match &state.cache {
+                    Some(cache) => {
+                        let cached_status = cache.folder_status(&login, &folder).ok().flatten();
+
+                        // UIDs of another UIDVALIDITY name different messages
+                        if cached_status.is_some_and(|s| s.uid_validity != status.uid_validity) {
+                            let _ = cache.clear_folder(&login, &folder);
+                        }
+
+                        // Nothing arrived or was removed since the page was cached
+                        cache
+                            .page(&login, &folder, page, page_size)
+                            .ok()
+                            .flatten()
+                            .filter(|(cached_status, _)| {
+                                *cached_status == status && status.uid_next != 0
+                            })
+                            .map(|(_, envelopes)| envelopes)
+                    }
+                    None => None,
                 };
 
-                let Ok(folders) = backends.0.list_folders().await else {
-                    return;
+                let envelopes = match cached_page {
+                    Some(envelopes) => {
+                        let envelopes = Self::refresh_envelopes(imap, &folder, envelopes).await?;
+
+                        if let Some(cache) = &state.cache {
+                            let _ = cache
+                                .store_page(&login, &folder, page, page_size, status, &envelopes);
+                        }
+
+                        envelopes
+                    }
+                    // email-lib fails on a page past the end of the folder
+                    None if page * page_size >= status.exists as usize => Vec::new(),
+                    None => {
+                        let envelopes = imap
+                            .list_envelopes(
+                                &folder,
+                                ListEnvelopesOptions {
+                                    page_size,
+                                    page,
+                                    query: None,
+                                },
+                            )
+                            .await
+                            .with_context(|| format!("Cannot list envelopes of {}", folder))?
+                            .to_vec();
+
+                        if let Some(cache) = &state.cache {
+                            let _ = cache
+                                .store_page(&login, &folder, page, page_size, status, &envelopes);
+                        }
+
+                        envelopes
+                    }
                 };
 
-                state.account_folders.write().await.insert(
-                    login,
-                    Some(folders.iter().map(|f| f.name.clone()).collect()),
-                );
-            }
+                if !state.is_latest_request(&request).await {
+                    return Ok(());
+                }
 
-            Actions::ListEnvelopes {
-                login,
-                folder,
-                page,
-            } => {
                 state
-                    .account_envelopes
-                    .write()
-                    .await
-                    .insert(login.clone(), None);
-
-                let Some(backends) = account_map.get(&login) else {
-                    return;
-                };
+                    .add_envelopes_page(
+                        &login,
+                        FolderEnvelopes::page(
+                            folder,
+                            None,
+                            Sort::DateDesc,
+                            page_size,
+                            page,
+                            envelopes,
+                        ),
+                    )
+                    .await;
+            }
 
-                let Ok(envelopes) = backends
-                    .0
-                    .list_envelopes(
+            (
+                Actions::ListEnvelopes {
+                    login,
+                    folder,
+                    page,
+                    page_size,
+                    query,
+                    sort,
+                    ..
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                let listed = Query::sorted(query.clone(), sort);
+
+                // email-lib always slices a query from its first match, so the
+                // pages up to this one are listed and the earlier ones dropped
+                let envelopes = if !listed.has_attachment {
+                    imap.list_envelopes(
                         &folder,
                         ListEnvelopesOptions {
-                            page_size: 10,
-                            page,
-                            query: None,
+                            page_size: (page + 1) * page_size,
+                            page: 0,
+                            query: Some(listed.search),
                         },
                     )
                     .await
-                else {
-                    return;
+                    .with_context(|| format!("Cannot search envelopes of {}", folder))?
+                    .iter()
+                    .skip(page * page_size)
+                    .cloned()
+                    .collect()
+                } else {
+                    let key = (login.clone(), folder.clone());
+
+                    let searched = match page {
+                        // The first page is a fresh look at the folder
+                        0 => None,
+                        _ => state
+                            .folder_searches
+                            .read()
+                            .await
+                            .get(&key)
+                            .and_then(|search| search.page(&query, sort, page, page_size)),
+                    };
+
+                    match searched {
+                        Some(envelopes) => envelopes,
+                        None => {
+                            // Attachments are filtered after listing, so a page of
+                            // matches can only be cut from all of them
+                            let search = FolderSearch {
+                                query: query.clone(),
+                                sort,
+                                envelopes: imap
+                                    .list_envelopes(
+                                        &folder,
+                                        ListEnvelopesOptions {
+                                            page_size: 0,
+                                            page: 0,
+                                            query: Some(listed.search),
+                                        },
+                                    )
+                                    .await
+                                    .with_context(|| {
+                                        format!("Cannot search envelopes of {}", folder)
+                                    })?
+                                    .iter()
+                                    .filter(|e| e.has_attachment)
+                                    .cloned()
+                                    .collect(),
+                            };
+
+                            let envelopes = search
+                                .page(&query, sort, page, page_size)
+                                .unwrap_or_default();
+
+                            state.folder_searches.write().await.insert(key, search);
+
+                            envelopes
+                        }
+                    }
                 };
 
+                if !state.is_latest_request(&request).await {
+                    return Ok(());
+                }
+
                 state
-                    .account_envelopes
-                    .write()
-                    .await
-                    .insert(login, Some(envelopes.to_vec()));
+                    .add_envelopes_page(
+                        &login,
+                        FolderEnvelopes::page(folder, query, sort, page_size, page, envelopes),
+                    )
+                    .await;
             }
 
-            Actions::GetMessage { login, folder, id } => {
-                *state.message.write().await = None;
+            (
+                Actions::GetMessage {
+                    login, folder, id, ..
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                // Bodies never change for a UID, so a cached one is final
+                if let Some(cache) = &state.cache {
+                    if cache.body(&login, &folder, &id).ok().flatten().is_some() {
+                        return Ok(());
+                    }
+                }
 
-                let Some(backends) = account_map.get(&login) else {
-                    return;
+                let messages = imap
+                    .get_messages(&folder, &Id::Single(id.clone().into()))
+                    .await
+                    .with_context(|| format!("Cannot get message {}", id))?;
+
+                let Some(message) = messages.first() else {
+                    bail!("Message {} not found", id)
                 };
 
-                let messages = backends
-                    .0
-                    .get_messages(&folder, &Id::Single(id.into()))
-                    .await;
+                let Ok(parsed_message) = message.parsed() else {
+                    bail!("Cannot parse message {}", id)
+                };
 
-                let Ok(messages) = messages else {
-                    return;
+                let Some(body_text) = parsed_message.body_text(0) else {
+                    bail!("Message {} has no text body", id)
                 };
 
-                let Some(message) = messages.first() else {
-                    return;
+                if let Some(cache) = &state.cache {
+                    let _ = cache.store_body(&login, &folder, &id, &body_text);
+                }
+
+                if !state.is_latest_request(&request).await {
+                    return Ok(());
+                }
+
+                state.account_messages.write().await.insert(
+                    login,
+                    Some(OpenMessage {
+                        folder,
+                        id,
+                        text: body_text.to_string(),
+                    }),
+                );
+            }
+
+            (
+                Actions::SendMessage {
+                    login,
+                    to,
+                    cc,
+                    bcc,
+                    subject,
+                    text,
+                    in_reply_to,
+                    references,
+                },
+                LaneBackend::Smtp(smtp),
+            ) => {
+                let Some(first) = to.iter().chain(&cc).chain(&bcc).next() else {
+                    bail!("Message has no recipients")
                 };
 
-                let Ok(parsed_message) = message.parsed() else {
-                    return;
+                let first = first.addr.clone();
+
+                let list = |mailboxes: &[Mailbox]| {
+                    Address::new_list(
+                        mailboxes
+                            .iter()
+                            .map(|m| Address::new_address(m.name.clone(), m.addr.clone()))
+                            .collect(),
+                    )
                 };
 
-                let Some(body_text) = parsed_message.body_text(0) else {
-                    return;
+                // The id is made up on the sender domain rather than the local hostname
+                let domain = login.rsplit_once('@').map_or("localhost", |(_, d)| d);
+                let message_id = format!("{}@{}", make_boundary("."), domain);
+                let date = chrono::Utc::now().timestamp();
+
+                // Recipients only see the message without Bcc, the Sent copy keeps it
+                let build = |bcc: &[Mailbox]| {
+                    let mut builder = MessageBuilder::new()
+                        .from(login.as_str())
+                        .message_id(message_id.as_str())
+                        .date(date)
+                        .subject(draft::single_line(&subject))
+                        .text_body(text.as_str());
+
+                    if !to.is_empty() {
+                        builder = builder.to(list(&to));
+                    }
+
+                    if !cc.is_empty() {
+                        builder = builder.cc(list(&cc));
+                    }
+
+                    if !bcc.is_empty() {
+                        builder = builder.bcc(list(bcc));
+                    }
+
+                    if let Some(in_reply_to) = &in_reply_to {
+                        builder = builder.in_reply_to(in_reply_to.as_str());
+                    }
+
+                    if !references.is_empty() {
+                        builder = builder.references(references.as_slice());
+                    }
+
+                    builder
+                        .write_to_vec()
+                        .with_context(|| format!("Cannot build message to {}", first))
                 };
 
-                *state.message.write().await = Some(body_text.to_string());
+                let raw = build(&[])?;
+
+                let mut rcpt_to: Vec<&str> = Vec::new();
+
+                for mailbox in to.iter().chain(&cc).chain(&bcc) {
+                    if !rcpt_to
+                        .iter()
+                        .any(|r| r.eq_ignore_ascii_case(&mailbox.addr))
+                    {
+                        rcpt_to.push(&mailbox.addr);
+                    }
+                }
+
+                smtp.send(SmtpMessage {
+                    mail_from: login.as_str().into(),
+                    rcpt_to: rcpt_to.into_iter().map(Into::into).collect(),
+                    body: raw.into(),
+                })
+                .await
+                .with_context(|| format!("Cannot send message to {}", first))?;
+
+                // The copy goes through the IMAP lane, failing to save it must not resend
+                let _ = actions_tx.send(Actions::SaveSentMessage {
+                    raw: build(&bcc)?,
+                    login,
+                });
             }
 
-            Actions::SendMessage {
-                login,
-                to,
-                subject,
-                text,
-            } => {
-                let Some(backends) = account_map.get(&login) else {
-                    return;
+            (Actions::SaveSentMessage { raw, .. }, LaneBackend::Imap(imap)) => {
+                let sent = Self::target_folder(imap, MoveTarget::Sent).await?;
+
+                imap.add_message_with_flag(&sent, &raw, Flag::Seen)
+                    .await
+                    .with_context(|| format!("Cannot save a copy to {}", sent))?;
+            }
+
+            (
+                Actions::PrepareDraft {
+                    login,
+                    folder,
+                    id,
+                    kind,
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                // Answering does not change whether the message was read
+                let messages = imap
+                    .peek_messages(&folder, &Id::single(id.clone()))
+                    .await
+                    .with_context(|| format!("Cannot get message {}", id))?;
+
+                let Some(message) = messages.first() else {
+                    bail!("Message {} not found", id)
                 };
 
-                if !EmailAddress::is_valid(&to) {
-                    return;
+                let Ok(parsed_message) = message.parsed() else {
+                    bail!("Cannot parse message {}", id)
+                };
+
+                *state.draft.write().await = Some(draft::answer(parsed_message, kind, &login));
+
+                state.notify_changed();
+            }
+
+            (
+                Actions::AddFlag {
+                    login,
+                    folder,
+                    ids,
+                    flag,
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                let id = Id::multiple(ids.clone());
+
+                imap.add_flag(&folder, &id, flag.clone())
+                    .await
+                    .with_context(|| format!("Cannot flag messages {} as {}", id, flag))?;
+
+                if let Some(cache) = &state.cache {
+                    let _ = cache.set_flag(&login, &folder, &ids, &flag, true);
+                }
+            }
+
+            (
+                Actions::RemoveFlag {
+                    login,
+                    folder,
+                    ids,
+                    flag,
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                let id = Id::multiple(ids.clone());
+
+                imap.remove_flag(&folder, &id, flag.clone())
+                    .await
+                    .with_context(|| format!("Cannot unflag messages {} as {}", id, flag))?;
+
+                if let Some(cache) = &state.cache {
+                    let _ = cache.set_flag(&login, &folder, &ids, &flag, false);
+                }
+            }
+
+            (
+                Actions::MoveMessages {
+                    login,
+                    folder,
+                    ids,
+                    target,
+                },
+                LaneBackend::Imap(imap),
+            ) => {
+                let to = Self::target_folder(imap, target).await?;
+
+                if to == folder {
+                    bail!("Messages are already in {}", folder)
+                }
+
+                let id = Id::multiple(ids.clone());
+
+                imap.move_messages(&folder, &to, &id)
+                    .await
+                    .with_context(|| format!("Cannot move messages {} to {}", id, to))?;
+
+                // Searches of both folders no longer match what is on the server
+                let mut searches = state.folder_searches.write().await;
+                searches.remove(&(login.clone(), folder.clone()));
+                searches.remove(&(login.clone(), to));
+                drop(searches);
+
+                if let Some(cache) = &state.cache {
+                    let _ = cache.remove_envelopes(&login, &folder, &ids);
+                }
+            }
+
+            (Actions::ExpungeMessages { login, folder, ids }, LaneBackend::Imap(imap)) => {
+                let id = Id::multiple(ids.clone());
+
+                imap.add_flag(&folder, &id, Flag::Deleted)
+                    .await
+                    .with_context(|| format!("Cannot delete messages {}", id))?;
+
+                imap.expunge_folder(&folder)
+                    .await
+                    .with_context(|| format!("Cannot expunge {}", folder))?;
+
+                state
+                    .folder_searches
+                    .write()
+                    .await
+                    .remove(&(login.clone(), folder.clone()));
+
+                if let Some(cache) = &state.cache {
+                    let _ = cache.remove_envelopes(&login, &folder, &ids);
                 }
+            }
+
+            (action, _) => bail!("{:?} dispatched to the wrong lane", action),
+        }
+
+        Ok(())
+    }
+}
+
+// Only a dead connection is worth a reconnect, an action the server refused
+// would fail the same way again
+fn is_connection_error(err: &anyhow::Error) -> bool {
+    use email::imap::Error as ImapError;
+
+    err.chain().any(|cause| {
+        // Backend features box their errors, and the box hides them from the chain
+        let imap_err = cause.downcast_ref::<ImapError>().or_else(|| {
+            cause
+                .downcast_ref::<AnyBoxedError>()
+                .and_then(|err| err.as_any().downcast_ref::<ImapError>())
+        });
+
+        cause.is::<io::Error>()
+            || matches!(
+                cause.downcast_ref::<mail_send::Error>(),
+                Some(mail_send::Error::Io(_) | mail_send::Error::Timeout)
+            )
+            || matches!(
+                imap_err,
+                Some(
+                    ImapError::BuildClientError(_)
+                        | ImapError::BuildInsecureClientError(..)
+                        | ImapError::BuildStartTlsClientError(..)
+                        | ImapError::BuildTlsClientError(..)
+                        | ImapError::RequestRetryTimeoutError
+                        | ImapError::SelectMailboxTimedOutError
+                        | ImapError::ExamineMailboxTimedOutError
+                        | ImapError::ListMailboxesTimedOutError
+                        | ImapError::ExpungeMailboxTimedOutError
+                        | ImapError::FetchMessagesTimedOutError
+                        | ImapError::StoreFlagsTimedOutError
+                        | ImapError::AddMessageTimedOutError
+                        | ImapError::MoveMessagesTimedOutError
+                        | ImapError::SearchUidsTimedOutError
+                        | ImapError::SortUidsTimedOutError
+                )
+            )
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Lane {
+    Imap,
+    Smtp,
+}
+
+impl Lane {
+    fn of(action: &Actions) -> Self {
+        match action {
+            Actions::SendMessage { .. } => Lane::Smtp,
+            _ => Lane::Imap,
+        }
+    }
+}
+
+enum LaneBackend {
+    Imap(Box<Backend<ImapContext>>),
+    Smtp(SmtpSender),
+}
 
-                let msg = [
-                    "Content-Type: text/plain",
-                    &format!("From: {}", login).to_string(),
-                    &format!("To: {}", to).to_string(),
-                    &format!("Subject: {}", subject).to_string(),
-                    "",
-                    &text,
-                ]
-                .join("\n");
+/// Sends with mail-send directly, email-lib takes the SMTP envelope from the
+/// message headers and so could only reach Bcc recipients through a Bcc header
+struct SmtpSender {
+    config: Arc<SmtpConfig>,
+}
 
-                let msg = Message::from(msg.as_str());
+impl SmtpSender {
+    async fn client_builder(&self) -> Result<SmtpClientBuilder<String>> {
+        Ok(
+            SmtpClientBuilder::new(self.config.host.clone(), self.config.port)
+                .credentials(self.config.credentials().await?)
+                .implicit_tls(!self.config.is_start_tls_encryption_enabled()),
+        )
+    }
 
-                let Ok(raw) = msg.raw() else {
-                    return;
+    async fn check(&self) -> Result<()> {
+        let builder = self.client_builder().await?;
+
+        if self.config.is_encryption_enabled() {
+            builder.connect().await?.quit().await?;
+        } else {
+            builder.connect_plain().await?.quit().await?;
+        }
+
+        Ok(())
+    }
+
+    // A connection per message, an idle one would only time out between sends
+    async fn send(&self, message: SmtpMessage<'_>) -> Result<()> {
+        let result = self.deliver(message.clone()).await;
+
+        let is_auth_error = |err: &anyhow::Error| {
+            matches!(
+                err.downcast_ref::<mail_send::Error>(),
+                Some(mail_send::Error::AuthenticationFailed(_))
+            )
+        };
+
+        // Nothing is sent before authenticating, so an expired token can be refreshed
+        match (&self.config.auth, result) {
+            (SmtpAuthConfig::OAuth2(oauth2), Err(err)) if is_auth_error(&err) => {
+                oauth2.refresh_access_token().await?;
+                self.deliver(message).await
+            }
+            (_, result) => result,
+        }
+    }
+
+    async fn deliver(&self, message: SmtpMessage<'_>) -> Result<()> {
+        let builder = self.client_builder().await?;
+
+        // Once the message is accepted, failing to say goodbye does not matter
+        if self.config.is_encryption_enabled() {
+            let mut client = builder.connect().await?;
+            client.send(message).await?;
+            let _ = client.quit().await;
+        } else {
+            let mut client = builder.connect_plain().await?;
+            client.send(message).await?;
+            let _ = client.quit().await;
+        }
+
+        Ok(())
+    }
+}
+
+// Every account has one worker per lane, so listing a folder never waits
+// on sending or on another account
+struct Worker {
+    account: Account,
+    lane: Lane,
+    server: ServerConfig,
+    backend: LaneBackend,
+    queue: Arc<ActionQueue>,
+    /// Dispatches the follow-up of an action, like saving a sent message
+    actions_tx: UnboundedSender<Actions>,
+}
+
+impl Worker {
+    async fn run(mut self, state: Arc<State>) {
+        loop {
+            let action = self.queue.pop().await;
+
+            let result = EmailBackend::execute_action(
+                &self.backend,
+                action.clone(),
+                &self.actions_tx,
+                state.clone(),
+            )
+            .await;
+
+            let result = match result {
+                Err(err) if is_connection_error(&err) => match self.reconnect().await {
+                    // The message may have gone out before the connection broke
+                    Ok(()) if matches!(action, Actions::SendMessage { .. }) => Err(err),
+                    Ok(()) => {
+                        EmailBackend::execute_action(
+                            &self.backend,
+                            action.clone(),
+                            &self.actions_tx,
+                            state.clone(),
+                        )
+                        .await
+                    }
+                    Err(reconnect_err) => Err(reconnect_err),
+                },
+                result => result,
+            };
+
+            if let Actions::SendMessage { .. } = action {
+                *state.send_status.write().await = Some(match &result {
+                    Ok(()) => SendStatus::Sent,
+                    Err(err) => SendStatus::Failed(format!("{:#}", err)),
+                });
+            }
+
+            if let (Err(_), Some(rollback)) = (&result, action.flag_rollback()) {
+                state.apply_flag(&rollback).await;
+            }
+
+            // A superseded request failing says nothing about the current selection
+            let is_latest = state.is_latest_request(&action).await;
+
+            match result {
+                Ok(()) => state.clear_error(action.login()).await,
+                Err(_) if !is_latest => {}
+                Err(err) => {
+                    state
+                        .report_error(action.login(), format!("{:#}", err))
+                        .await
+                }
+            }
+
+            self.queue.finish().await;
+
+            // Only reads are shown as loading, sending has its own status
+            if self.lane == Lane::Imap && self.queue.is_idle().await {
+                state.set_updating(&self.account.login, false).await;
+            }
+        }
+    }
+
+    // Rebuilds the backend of this lane, backing off between attempts
+    async fn reconnect(&mut self) -> Result<()> {
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        let mut attempt = 1;
+
+        loop {
+            let result = async {
+                let credentials = EmailBackend::credentials(&self.account).await?;
+
+                self.backend = match self.lane {
+                    Lane::Imap => LaneBackend::Imap(Box::new(
+                        EmailBackend::build_imap(&self.account, &credentials, &self.server).await?,
+                    )),
+                    Lane::Smtp => LaneBackend::Smtp(
+                        EmailBackend::build_smtp(&self.account, &credentials, &self.server).await?,
+                    ),
                 };
 
-                let _ = backends.1.send_message_then_save_copy(raw).await;
+                Ok::<_, anyhow::Error>(())
+            }
+            .await;
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt == RECONNECT_ATTEMPTS => {
+                    return Err(err.context(format!(
+                        "Cannot reconnect {} after {} attempts",
+                        self.account.login, RECONNECT_ATTEMPTS
+                    )));
+                }
+                Err(_) => {
+                    sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+    use email::imap::Error as ImapError;
+
+    use super::*;
+
+    #[test]
+    fn boxed_imap_timeouts_are_connection_errors() {
+        let listed: Result<(), AnyBoxedError> = Err(ImapError::ListMailboxesTimedOutError.into());
+        let err = listed.context("Cannot list folders").unwrap_err();
+
+        assert!(is_connection_error(&err));
+    }
+
+    #[test]
+    fn refused_actions_are_not_connection_errors() {
+        let err = anyhow!("Cannot move messages 1 to Trash");
+
+        assert!(!is_connection_error(&err));
+    }
+}
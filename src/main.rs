@@ -1,7 +1,12 @@
 mod actions;
 mod app;
 mod args;
+mod cache;
+mod config;
+mod draft;
 mod email;
+mod query;
+mod queue;
 mod state;
 mod ui;
 
@@ -17,6 +22,8 @@ use tokio::sync::mpsc::UnboundedSender;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    secret::keyring::set_global_service_name("tldr-email");
+
     let state = get_initial_state_from_args().await?;
     let actions_tx = spawn_email_backend_task(state.clone()).await?;
 
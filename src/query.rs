@@ -0,0 +1,472 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, NaiveDate};
+use email::{
+    envelope::{list::ListEnvelopesOptions, Envelope, Envelopes, Flag},
+    search_query::{
+        filter::SearchEmailsFilterQuery,
+        sort::{SearchEmailsSorter, SearchEmailsSorterKind, SearchEmailsSorterOrder},
+        SearchEmailsQuery,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::default_config_path;
+
+/// A parsed search of the message list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub search: SearchEmailsQuery,
+    /// IMAP cannot search for attachments, so envelopes are filtered instead
+    pub has_attachment: bool,
+}
+
+/// Order of the messages table, toggled per folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    #[default]
+    DateDesc,
+    DateAsc,
+    From,
+    Subject,
+}
+
+impl Sort {
+    pub fn next(self) -> Self {
+        match self {
+            Sort::DateDesc => Sort::DateAsc,
+            Sort::DateAsc => Sort::From,
+            Sort::From => Sort::Subject,
+            Sort::Subject => Sort::DateDesc,
+        }
+    }
+
+    fn sorter(self) -> SearchEmailsSorter {
+        match self {
+            Sort::DateDesc => SearchEmailsSorter::new(
+                SearchEmailsSorterKind::Date,
+                SearchEmailsSorterOrder::Descending,
+            ),
+            Sort::DateAsc => SearchEmailsSorter::new(
+                SearchEmailsSorterKind::Date,
+                SearchEmailsSorterOrder::Ascending,
+            ),
+            Sort::From => SearchEmailsSorter::new(
+                SearchEmailsSorterKind::From,
+                SearchEmailsSorterOrder::Ascending,
+            ),
+            Sort::Subject => SearchEmailsSorter::new(
+                SearchEmailsSorterKind::Subject,
+                SearchEmailsSorterOrder::Ascending,
+            ),
+        }
+    }
+}
+
+/// A query shown as a virtual folder after the folders of its account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub login: String,
+    pub name: String,
+    pub folder: String,
+    pub query: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedQueries {
+    #[serde(default)]
+    queries: Vec<SavedQuery>,
+}
+
+/// Saved queries are kept next to the config file in use
+pub fn saved_queries_path(config: Option<&Path>) -> Option<PathBuf> {
+    let config = match config {
+        Some(config) => config.to_path_buf(),
+        None => default_config_path()?,
+    };
+
+    Some(config.with_file_name("queries.toml"))
+}
+
+pub fn load_saved_queries(path: &Path) -> Result<Vec<SavedQuery>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read saved queries {}", path.display()))?;
+
+    let saved: SavedQueries = toml::from_str(&content)
+        .with_context(|| format!("cannot parse saved queries {}", path.display()))?;
+
+    Ok(saved.queries)
+}
+
+pub fn store_saved_queries(path: &Path, queries: &[SavedQuery]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("cannot create config directory {}", dir.display()))?;
+    }
+
+    let content = toml::to_string(&SavedQueries {
+        queries: queries.to_vec(),
+    })?;
+
+    std::fs::write(path, content)
+        .with_context(|| format!("cannot write saved queries {}", path.display()))
+}
+
+/// Parses `from:alice subject:"invoice" is:unread after:2026-01-01 has:attachment`.
+///
+/// Terms are and-ed, a leading `-` negates one. Plain words, and words like
+/// `Re:` whose key is not known, are searched in the subject, the sender, the
+/// recipient and the body. `sort:date`, `sort:from`,
+/// `sort:to` and `sort:subject` take an optional `-asc` or `-desc` suffix.
+pub fn parse(text: &str) -> Result<Query> {
+    let mut filters = Vec::new();
+    let mut sort = Vec::new();
+    let mut has_attachment = false;
+
+    for term in tokenize(text)? {
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(term) if !term.is_empty() => (true, term.to_string()),
+            _ => (false, term),
+        };
+
+        let filter = match term.split_once(':') {
+            Some(("from", value)) => SearchEmailsFilterQuery::From(value.to_string()),
+            Some(("to", value)) => SearchEmailsFilterQuery::To(value.to_string()),
+            Some(("subject", value)) => SearchEmailsFilterQuery::Subject(value.to_string()),
+            Some(("body", value)) => SearchEmailsFilterQuery::Body(value.to_string()),
+            Some(("on", value)) => SearchEmailsFilterQuery::Date(parse_date(value)?),
+            Some(("before", value)) => SearchEmailsFilterQuery::BeforeDate(parse_date(value)?),
+            Some(("after", value)) => {
+                let date = parse_date(value)?;
+
+                // email-lib searches from the next day, which has to exist too
+                if date.succ_opt().is_none_or(|next| next.year() > 9999) {
+                    bail!("invalid date {}. Nothing is sent after it", value);
+                }
+
+                SearchEmailsFilterQuery::AfterDate(date)
+            }
+            Some(("is", value)) => parse_is(value)?,
+            Some(("has" | "sort", _)) if negated => bail!("cannot negate {}", term),
+            Some(("has", "attachment")) => {
+                has_attachment = true;
+                continue;
+            }
+            Some(("has", value)) => bail!("unknown search has:{}", value),
+            Some(("sort", value)) => {
+                sort.push(parse_sort(value)?);
+                continue;
+            }
+            _ => text_filter(&term),
+        };
+
+        filters.push(if negated {
+            SearchEmailsFilterQuery::Not(Box::new(filter))
+        } else {
+            filter
+        });
+    }
+
+    let filter = filters
+        .into_iter()
+        .reduce(|a, b| SearchEmailsFilterQuery::And(Box::new(a), Box::new(b)));
+
+    Ok(Query {
+        search: SearchEmailsQuery {
+            filter,
+            sort: Some(sort).filter(|s| !s.is_empty()),
+        },
+        has_attachment,
+    })
+}
+
+impl Query {
+    /// Lists the whole folder when there is no query. A `sort:` of the query
+    /// wins over the sort of the folder.
+    pub fn sorted(query: Option<Query>, sort: Sort) -> Self {
+        let mut query = query.unwrap_or(Query {
+            search: SearchEmailsQuery {
+                filter: None,
+                sort: None,
+            },
+            has_attachment: false,
+        });
+
+        if query.search.sort.is_none() {
+            query.search.sort = Some(vec![sort.sorter()]);
+        }
+
+        query
+    }
+
+    /// Evaluates the query on cached mail the way the IMAP server would.
+    /// Messages whose body is not cached never match a body condition.
+    pub fn matches(&self, envelope: &Envelope, body: Option<&str>) -> bool {
+        (!self.has_attachment || envelope.has_attachment)
+            && self
+                .search
+                .filter
+                .as_ref()
+                .is_none_or(|filter| matches(filter, envelope, body))
+    }
+
+    pub fn sort(&self, envelopes: Vec<Envelope>) -> Vec<Envelope> {
+        let mut envelopes: Envelopes = envelopes.into_iter().collect();
+
+        ListEnvelopesOptions {
+            page_size: 0,
+            page: 0,
+            query: Some(self.search.clone()),
+        }
+        .sort_envelopes(&mut envelopes);
+
+        envelopes.into()
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut is_quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => is_quoted = !is_quoted,
+            c if c.is_whitespace() && !is_quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+
+    if is_quoted {
+        bail!("unclosed quote in search");
+    }
+
+    if !term.is_empty() {
+        terms.push(term);
+    }
+
+    Ok(terms)
+}
+
+fn text_filter(text: &str) -> SearchEmailsFilterQuery {
+    [
+        SearchEmailsFilterQuery::Subject(text.to_string()),
+        SearchEmailsFilterQuery::From(text.to_string()),
+        SearchEmailsFilterQuery::To(text.to_string()),
+        SearchEmailsFilterQuery::Body(text.to_string()),
+    ]
+    .into_iter()
+    .reduce(|a, b| SearchEmailsFilterQuery::Or(Box::new(a), Box::new(b)))
+    .unwrap()
+}
+
+// IMAP dates have a four digit year, email-lib panics on any other
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    let is_four_digit_year = value
+        .split_once('-')
+        .is_some_and(|(year, _)| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()));
+
+    if !is_four_digit_year {
+        bail!("invalid date {}. Expected YYYY-MM-DD", value);
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("invalid date {}. Expected YYYY-MM-DD", value))
+}
+
+fn parse_is(value: &str) -> Result<SearchEmailsFilterQuery> {
+    let flag = |flag| SearchEmailsFilterQuery::Flag(flag);
+    let not = |filter| SearchEmailsFilterQuery::Not(Box::new(filter));
+
+    match value {
+        "read" | "seen" => Ok(flag(Flag::Seen)),
+        "unread" | "unseen" => Ok(not(flag(Flag::Seen))),
+        "flagged" | "starred" => Ok(flag(Flag::Flagged)),
+        "answered" | "replied" => Ok(flag(Flag::Answered)),
+        "draft" => Ok(flag(Flag::Draft)),
+        "deleted" => Ok(flag(Flag::Deleted)),
+        _ => bail!("unknown search is:{}", value),
+    }
+}
+
+fn parse_sort(value: &str) -> Result<SearchEmailsSorter> {
+    let (kind, order) = match value.rsplit_once('-') {
+        Some((kind, "asc")) => (kind, Some(SearchEmailsSorterOrder::Ascending)),
+        Some((kind, "desc")) => (kind, Some(SearchEmailsSorterOrder::Descending)),
+        _ => (value, None),
+    };
+
+    let kind = match kind {
+        "date" => SearchEmailsSorterKind::Date,
+        "from" => SearchEmailsSorterKind::From,
+        "to" => SearchEmailsSorterKind::To,
+        "subject" => SearchEmailsSorterKind::Subject,
+        _ => bail!("unknown sort {}", value),
+    };
+
+    // Newest first unless asked otherwise, like the folder itself
+    let order = order.unwrap_or(match kind {
+        SearchEmailsSorterKind::Date => SearchEmailsSorterOrder::Descending,
+        _ => SearchEmailsSorterOrder::Ascending,
+    });
+
+    Ok(SearchEmailsSorter::new(kind, order))
+}
+
+fn matches(filter: &SearchEmailsFilterQuery, envelope: &Envelope, body: Option<&str>) -> bool {
+    let contains =
+        |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+
+    match filter {
+        SearchEmailsFilterQuery::And(a, b) => {
+            matches(a, envelope, body) && matches(b, envelope, body)
+        }
+        SearchEmailsFilterQuery::Or(a, b) => {
+            matches(a, envelope, body) || matches(b, envelope, body)
+        }
+        SearchEmailsFilterQuery::Not(filter) => !matches(filter, envelope, body),
+        SearchEmailsFilterQuery::Date(date) => envelope.date.date_naive() == *date,
+        SearchEmailsFilterQuery::BeforeDate(date) => envelope.date.date_naive() < *date,
+        SearchEmailsFilterQuery::AfterDate(date) => envelope.date.date_naive() > *date,
+        SearchEmailsFilterQuery::From(pattern) => {
+            contains(&envelope.from.addr, pattern)
+                || envelope
+                    .from
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| contains(name, pattern))
+        }
+        SearchEmailsFilterQuery::To(pattern) => {
+            contains(&envelope.to.addr, pattern)
+                || envelope
+                    .to
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| contains(name, pattern))
+        }
+        SearchEmailsFilterQuery::Subject(pattern) => contains(&envelope.subject, pattern),
+        SearchEmailsFilterQuery::Body(pattern) => body.is_some_and(|body| contains(body, pattern)),
+        SearchEmailsFilterQuery::Flag(flag) => envelope.flags.contains(flag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(text: &str) -> SearchEmailsFilterQuery {
+        parse(text).unwrap().search.filter.unwrap()
+    }
+
+    fn not(filter: SearchEmailsFilterQuery) -> SearchEmailsFilterQuery {
+        SearchEmailsFilterQuery::Not(Box::new(filter))
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        assert_eq!(
+            filter(r#"subject:"monthly invoice""#),
+            SearchEmailsFilterQuery::Subject("monthly invoice".to_string())
+        );
+        assert_eq!(filter(r#""hello world""#), text_filter("hello world"));
+    }
+
+    #[test]
+    fn terms_are_and_ed() {
+        assert_eq!(
+            filter("from:alice is:unread"),
+            SearchEmailsFilterQuery::And(
+                Box::new(SearchEmailsFilterQuery::From("alice".to_string())),
+                Box::new(not(SearchEmailsFilterQuery::Flag(Flag::Seen))),
+            )
+        );
+    }
+
+    #[test]
+    fn a_leading_dash_negates_a_term() {
+        assert_eq!(
+            filter("-from:alice"),
+            not(SearchEmailsFilterQuery::From("alice".to_string()))
+        );
+        assert_eq!(filter("-"), text_filter("-"));
+    }
+
+    #[test]
+    fn dates_are_parsed() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 31).unwrap();
+
+        assert_eq!(filter("on:2026-01-31"), SearchEmailsFilterQuery::Date(date));
+        assert_eq!(
+            filter("before:2026-01-31"),
+            SearchEmailsFilterQuery::BeforeDate(date)
+        );
+        assert_eq!(
+            filter("after:2026-01-31"),
+            SearchEmailsFilterQuery::AfterDate(date)
+        );
+        assert!(parse("on:9999-12-31 after:9999-12-30 before:0000-01-01").is_ok());
+    }
+
+    #[test]
+    fn unknown_keys_are_plain_text() {
+        assert_eq!(filter("Re:"), text_filter("Re:"));
+        assert_eq!(
+            filter("http://example.com"),
+            text_filter("http://example.com")
+        );
+        assert_eq!(filter("-cc:bob"), not(text_filter("cc:bob")));
+    }
+
+    #[test]
+    fn attachments_and_sorts_are_not_filters() {
+        let query = parse("has:attachment sort:from-desc").unwrap();
+
+        assert!(query.has_attachment);
+        assert_eq!(query.search.filter, None);
+        assert_eq!(
+            query.search.sort,
+            Some(vec![SearchEmailsSorter::new(
+                SearchEmailsSorterKind::From,
+                SearchEmailsSorterOrder::Descending,
+            )])
+        );
+    }
+
+    #[test]
+    fn bad_values_of_known_keys_are_errors() {
+        for text in [
+            "after:yesterday",
+            "on:2026-13-01",
+            "is:important",
+            "has:link",
+            "-has:attachment",
+            "after:-0001-01-01",
+            "on:+10000-01-01",
+            "before:10000-01-01",
+            "after:9999-12-31",
+            "on:99-01-01",
+            "sort:size",
+            "-sort:date",
+            r#"subject:"unclosed"#,
+        ] {
+            assert!(parse(text).is_err(), "{} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn saved_queries_are_next_to_the_config() {
+        assert_eq!(
+            saved_queries_path(Some(Path::new("/etc/mail/work.toml"))),
+            Some(PathBuf::from("/etc/mail/queries.toml"))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+use tokio::sync::{Mutex, Notify};
+
+use crate::actions::Actions;
+
+/// Pending backend actions in arrival order.
+///
+/// A read action replaces the queued one with the same key and moves to the
+/// back, so only the latest folder listing or message fetch is executed.
+/// Writes are never dropped.
+#[derive(Debug, Default)]
+pub struct ActionQueue {
+    inner: Mutex<ActionQueueInner>,
+    notify: Notify,
+}
+
+#[derive(Debug, Default)]
+struct ActionQueueInner {
+    actions: VecDeque<Actions>,
+    is_busy: bool,
+}
+
+impl ActionQueue {
+    pub async fn push(&self, action: Actions) {
+        let mut inner = self.inner.lock().await;
+
+        // The latest read goes last, so it sees the writes queued before it
+        if let Some(key) = action.key() {
+            inner.actions.retain(|a| a.key().as_ref() != Some(&key));
+        }
+
+        inner.actions.push_back(action);
+
+        drop(inner);
+
+        self.notify.notify_one();
+    }
+
+    // Waits until an action is available, the queue stays busy until `finish`
+    pub async fn pop(&self) -> Actions {
+        loop {
+            {
+                let mut inner = self.inner.lock().await;
+
+                if let Some(action) = inner.actions.pop_front() {
+                    inner.is_busy = true;
+                    return action;
+                }
+            }
+
+            self.notify.notified().await;
+        }
+    }
+
+    pub async fn finish(&self) {
+        self.inner.lock().await.is_busy = false;
+    }
+
+    pub async fn drain(&self) -> Vec<Actions> {
+        self.inner.lock().await.actions.drain(..).collect()
+    }
+
+    pub async fn is_idle(&self) -> bool {
+        let inner = self.inner.lock().await;
+
+        inner.actions.is_empty() && !inner.is_busy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use email::envelope::Flag;
+
+    use super::*;
+
+    fn get_message(id: &str, request_id: u64) -> Actions {
+        Actions::GetMessage {
+            login: "me@inbox.com".to_string(),
+            folder: "INBOX".to_string(),
+            id: id.to_string(),
+            request_id,
+        }
+    }
+
+    fn add_flag(id: &str) -> Actions {
+        Actions::AddFlag {
+            login: "me@inbox.com".to_string(),
+            folder: "INBOX".to_string(),
+            ids: vec![id.to_string()],
+            flag: Flag::Seen,
+        }
+    }
+
+    // The id of a fetch, or the flagged id of a write
+    fn ids(actions: Vec<Actions>) -> Vec<String> {
+        actions
+            .into_iter()
+            .map(|action| match action {
+                Actions::GetMessage { id, .. } => id,
+                Actions::AddFlag { ids, .. } => format!("flag {}", ids.join(",")),
+                action => panic!("unexpected {:?}", action),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pops_in_arrival_order() {
+        let queue = ActionQueue::default();
+
+        queue.push(add_flag("1")).await;
+        queue.push(get_message("2", 1)).await;
+
+        assert_eq!(ids(vec![queue.pop().await]), ["flag 1"]);
+        assert!(!queue.is_idle().await);
+
+        queue.finish().await;
+        assert_eq!(ids(vec![queue.pop().await]), ["2"]);
+
+        queue.finish().await;
+        assert!(queue.is_idle().await);
+    }
+
+    #[tokio::test]
+    async fn a_read_replaces_the_queued_one_with_the_same_key() {
+        let queue = ActionQueue::default();
+
+        queue.push(get_message("1", 1)).await;
+        queue.push(get_message("2", 2)).await;
+
+        assert_eq!(ids(queue.drain().await), ["2"]);
+    }
+
+    #[tokio::test]
+    async fn a_superseding_read_moves_behind_the_queued_writes() {
+        let queue = ActionQueue::default();
+
+        queue.push(get_message("1", 1)).await;
+        queue.push(add_flag("1")).await;
+        queue.push(get_message("2", 2)).await;
+
+        assert_eq!(ids(queue.drain().await), ["flag 1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn writes_are_never_coalesced() {
+        let queue = ActionQueue::default();
+
+        queue.push(add_flag("1")).await;
+        queue.push(get_message("1", 1)).await;
+        queue.push(add_flag("1")).await;
+        queue.push(get_message("2", 2)).await;
+        queue.push(add_flag("2")).await;
+
+        assert_eq!(
+            ids(queue.drain().await),
+            ["flag 1", "flag 1", "2", "flag 2"]
+        );
+    }
+}
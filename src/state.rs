@@ -1,45 +1,205 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
 
+use anyhow::{bail, Result};
 use email::envelope::Envelope;
-use secret::Secret;
 use tokio::sync::{
     mpsc::{self, UnboundedSender},
-    RwLock,
+    Notify, RwLock,
 };
 use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
 
-use crate::actions::Actions;
+use crate::{
+    actions::{Actions, RequestSlot},
+    cache::Cache,
+    config::{AccountConfig, OAuth2Config, PasswordSource, ServerConfig},
+    draft::Draft,
+    query::{self, Query, SavedQuery, Sort},
+};
 
 #[derive(Debug, Clone)]
 pub struct Account {
     pub login: String,
-    pub password: Secret,
+    pub display_name: Option<String>,
+    pub password: Option<PasswordSource>,
+    pub oauth2: Option<OAuth2Config>,
+    pub imap: Option<ServerConfig>,
+    pub smtp: Option<ServerConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendStatus {
+    Sending,
+    Sent,
+    Failed(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum AccountStatus {
+    #[default]
+    Connecting,
+    Ready,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FolderEnvelopes {
+    pub folder: String,
+    pub query: Option<Query>,
+    pub sort: Sort,
+    pub page_size: usize,
+    /// Pages loaded so far, scrolling past them loads the next one
+    pub pages: usize,
+    pub has_more: bool,
+    pub envelopes: Vec<Envelope>,
+}
+
+impl FolderEnvelopes {
+    pub fn page(
+        folder: String,
+        query: Option<Query>,
+        sort: Sort,
+        page_size: usize,
+        page: usize,
+        envelopes: Vec<Envelope>,
+    ) -> Self {
+        Self {
+            folder,
+            query,
+            sort,
+            page_size,
+            pages: page + 1,
+            has_more: envelopes.len() == page_size,
+            envelopes,
+        }
+    }
+
+    fn is_listing_of(&self, other: &FolderEnvelopes) -> bool {
+        self.folder == other.folder
+            && self.query == other.query
+            && self.sort == other.sort
+            && self.page_size == other.page_size
+    }
+}
+
+/// Every match of a `has:attachment` search of a folder in order. The first
+/// page searches the server, later ones are cut from the result.
+#[derive(Debug, Clone)]
+pub struct FolderSearch {
+    pub query: Option<Query>,
+    pub sort: Sort,
+    pub envelopes: Vec<Envelope>,
+}
+
+impl FolderSearch {
+    pub fn page(
+        &self,
+        query: &Option<Query>,
+        sort: Sort,
+        page: usize,
+        page_size: usize,
+    ) -> Option<Vec<Envelope>> {
+        if &self.query != query || self.sort != sort {
+            return None;
+        }
+
+        Some(
+            self.envelopes
+                .iter()
+                .skip(page * page_size)
+                .take(page_size)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenMessage {
+    pub folder: String,
+    pub id: String,
+    pub text: String,
+}
+
+/// What is currently selected in the UI, results for anything else are not shown
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+    pub login: String,
+    pub folder: Option<String>,
+    pub query: Option<Query>,
+    pub sort: Sort,
+    pub message_id: Option<String>,
 }
 
 #[derive(Debug, Default)]
 pub struct ViewState {
     pub accounts: Vec<String>,
+    pub account_names: Vec<String>,
+    pub account_statuses: Vec<AccountStatus>,
     pub folders: Option<Vec<String>>,
+    pub saved_queries: Vec<SavedQuery>,
     pub messages: Option<Vec<Envelope>>,
+    pub messages_pages: usize,
+    pub messages_page_size: usize,
+    pub has_more_messages: bool,
     pub message: Option<String>,
+    pub error: Option<String>,
+    pub notification: Option<String>,
+    pub send_status: Option<SendStatus>,
+    pub is_updating: bool,
 }
 
 #[derive(Debug, Default)]
 pub struct State {
     pub accounts: RwLock<Vec<Account>>,
     pub account_folders: RwLock<HashMap<String, Option<Vec<String>>>>,
-    pub account_envelopes: RwLock<HashMap<String, Option<Vec<Envelope>>>>,
-    pub message: RwLock<Option<String>>,
-    pub is_updating: Arc<RwLock<bool>>,
+    pub account_envelopes: RwLock<HashMap<String, Option<FolderEnvelopes>>>,
+    /// Keyed by login and folder, only the latest search of a folder is kept
+    pub folder_searches: RwLock<HashMap<(String, String), FolderSearch>>,
+    pub account_messages: RwLock<HashMap<String, Option<OpenMessage>>>,
+    pub account_is_updating: RwLock<HashMap<String, bool>>,
+    pub account_statuses: RwLock<HashMap<String, AccountStatus>>,
+    pub account_errors: RwLock<HashMap<String, String>>,
+    latest_requests: RwLock<HashMap<RequestSlot, u64>>,
+    pub notifications: RwLock<VecDeque<String>>,
+    pub send_status: RwLock<Option<SendStatus>>,
+    /// Answer prepared by the backend, taken by the app into the compose form
+    pub draft: RwLock<Option<Draft>>,
+    pub cache: Option<Cache>,
+    pub page_size: Option<usize>,
+    pub saved_queries: RwLock<Vec<SavedQuery>>,
+    /// None when there is no home directory to keep them in
+    pub saved_queries_path: Option<PathBuf>,
     email_backend_tx: Arc<RwLock<Option<UnboundedSender<Actions>>>>,
+    changed: Notify,
 }
 
 impl State {
-    pub async fn add_account(&self, login: String, password: Secret) {
-        self.accounts
-            .write()
-            .await
-            .push(Account { login, password });
+    pub fn new(
+        cache: Option<Cache>,
+        page_size: Option<usize>,
+        saved_queries_path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            cache,
+            page_size: page_size.filter(|s| *s > 0),
+            saved_queries_path,
+            ..Default::default()
+        }
+    }
+
+    pub async fn add_account(&self, config: AccountConfig) {
+        self.accounts.write().await.push(Account {
+            login: config.login,
+            display_name: config.display_name,
+            password: config.password,
+            oauth2: config.oauth2,
+            imap: config.imap,
+            smtp: config.smtp,
+        });
     }
 
     pub async fn spawn_email_action_forwarder(
@@ -55,7 +215,6 @@ impl State {
 
         tokio::task::spawn(async move {
             while let Some(action) = rx.next().await {
-                eprintln!("{:?}", action);
                 let _ = email_backend_tx.read().await.as_ref().unwrap().send(action);
             }
         });
@@ -63,12 +222,212 @@ impl State {
         sync_tx
     }
 
-    pub async fn is_updating(&self) -> bool {
-        *self.is_updating.read().await
+    pub async fn set_account_status(&self, login: &str, status: AccountStatus) {
+        self.account_statuses
+            .write()
+            .await
+            .insert(login.to_string(), status);
+
+        self.notify_changed();
+    }
+
+    pub async fn account_status(&self, login: &str) -> Option<AccountStatus> {
+        self.account_statuses.read().await.get(login).cloned()
+    }
+
+    async fn account_statuses_view(&self) -> Vec<AccountStatus> {
+        let account_statuses = self.account_statuses.read().await;
+
+        self.accounts
+            .read()
+            .await
+            .iter()
+            .map(|a| account_statuses.get(&a.login).cloned().unwrap_or_default())
+            .collect()
+    }
+
+    pub async fn report_error(&self, login: &str, error: String) {
+        self.account_errors
+            .write()
+            .await
+            .insert(login.to_string(), error.clone());
+
+        self.notifications
+            .write()
+            .await
+            .push_back(format!("{}: {}", login, error));
+
+        self.notify_changed();
+    }
+
+    pub async fn clear_error(&self, login: &str) {
+        self.account_errors.write().await.remove(login);
+
+        self.notify_changed();
+    }
+
+    pub async fn dismiss_notification(&self) {
+        self.notifications.write().await.pop_front();
+    }
+
+    pub async fn is_updating(&self, login: &str) -> bool {
+        self.account_is_updating
+            .read()
+            .await
+            .get(login)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub async fn set_updating(&self, login: &str, is_updating: bool) {
+        self.account_is_updating
+            .write()
+            .await
+            .insert(login.to_string(), is_updating);
+
+        self.notify_changed();
+    }
+
+    // Wakes the app up to redraw, a change made while it is drawing is kept
+    // until it waits again
+    pub fn notify_changed(&self) {
+        self.changed.notify_one();
+    }
+
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    // A query saved again under the same name replaces the old one
+    pub async fn save_query(&self, saved: SavedQuery) -> Result<()> {
+        let Some(path) = &self.saved_queries_path else {
+            bail!("cannot find the config directory");
+        };
+
+        let mut saved_queries = self.saved_queries.write().await;
+
+        saved_queries.retain(|q| q.login != saved.login || q.name != saved.name);
+        saved_queries.push(saved);
+
+        query::store_saved_queries(path, &saved_queries)?;
+
+        drop(saved_queries);
+        self.notify_changed();
+
+        Ok(())
+    }
+
+    /// Adds a listing holding the rows of its last page only. The first page
+    /// starts a new listing, a later one replaces the rows of that page and
+    /// everything after it.
+    pub async fn add_envelopes_page(&self, login: &str, page: FolderEnvelopes) {
+        let mut account_envelopes = self.account_envelopes.write().await;
+        let current = account_envelopes.entry(login.to_string()).or_default();
+
+        let idx = page.pages - 1;
+
+        match current {
+            Some(current) if current.is_listing_of(&page) && idx <= current.pages => {
+                current.envelopes.truncate(idx * current.page_size);
+                current.envelopes.extend(page.envelopes);
+                current.pages = page.pages;
+                current.has_more = page.has_more;
+            }
+            _ if idx == 0 => *current = Some(page),
+            // The listing this page belongs to is not shown anymore
+            _ => {}
+        }
+    }
+
+    /// Shows a flag change on the listed envelopes before the server confirms it
+    pub async fn apply_flag(&self, action: &Actions) {
+        let (login, folder, ids, flag, is_set) = match action {
+            Actions::AddFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            } => (login, folder, ids, flag, true),
+            Actions::RemoveFlag {
+                login,
+                folder,
+                ids,
+                flag,
+            } => (login, folder, ids, flag, false),
+            _ => return,
+        };
+
+        let apply = |envelopes: &mut Vec<Envelope>| {
+            for envelope in envelopes.iter_mut().filter(|e| ids.contains(&e.id)) {
+                if is_set {
+                    envelope.flags.insert(flag.clone());
+                } else {
+                    envelope.flags.remove(flag);
+                }
+            }
+        };
+
+        // Later pages of a search are cut from it, so it has to agree with the table
+        if let Some(search) = self
+            .folder_searches
+            .write()
+            .await
+            .get_mut(&(login.clone(), folder.clone()))
+        {
+            apply(&mut search.envelopes);
+        }
+
+        let mut account_envelopes = self.account_envelopes.write().await;
+
+        let Some(Some(listing)) = account_envelopes.get_mut(login) else {
+            return;
+        };
+
+        if &listing.folder != folder {
+            return;
+        }
+
+        apply(&mut listing.envelopes);
+
+        drop(account_envelopes);
+        self.notify_changed();
+    }
+
+    pub async fn track_request(&self, action: &Actions) {
+        if let Some((slot, request_id)) = action.request() {
+            self.latest_requests.write().await.insert(slot, request_id);
+        }
     }
 
-    pub async fn as_view_state(&self, login: Option<String>) -> ViewState {
-        if let Some(login) = login {
+    // Results of superseded requests are dropped so they never overwrite a newer selection
+    pub async fn is_latest_request(&self, action: &Actions) -> bool {
+        let Some((slot, request_id)) = action.request() else {
+            return true;
+        };
+
+        self.latest_requests
+            .read()
+            .await
+            .get(&slot)
+            .is_none_or(|latest| request_id >= *latest)
+    }
+
+    pub async fn as_view_state(&self, selection: Option<Selection>) -> ViewState {
+        if let Some(selection) = selection {
+            let login = selection.login;
+            let is_updating = self.is_updating(&login).await;
+
+            let account_envelopes = self.account_envelopes.read().await;
+
+            let listing = account_envelopes
+                .get(&login)
+                .and_then(|e| e.as_ref())
+                .filter(|e| {
+                    Some(&e.folder) == selection.folder.as_ref()
+                        && e.query == selection.query
+                        && e.sort == selection.sort
+                });
+
             ViewState {
                 accounts: self
                     .accounts
@@ -77,6 +436,14 @@ impl State {
                     .iter()
                     .map(|a| a.login.clone())
                     .collect(),
+                account_names: self
+                    .accounts
+                    .read()
+                    .await
+                    .iter()
+                    .map(|a| a.display_name.clone().unwrap_or_else(|| a.login.clone()))
+                    .collect(),
+                account_statuses: self.account_statuses_view().await,
                 folders: self
                     .account_folders
                     .read()
@@ -84,14 +451,39 @@ impl State {
                     .get(&login)
                     .unwrap_or(&None)
                     .clone(),
-                messages: self
-                    .account_envelopes
+                saved_queries: self
+                    .saved_queries
+                    .read()
+                    .await
+                    .iter()
+                    .filter(|q| q.login == login)
+                    .cloned()
+                    .collect(),
+                messages: listing.map(|e| e.envelopes.clone()),
+                messages_pages: listing.map_or(0, |e| e.pages),
+                messages_page_size: listing.map_or(0, |e| e.page_size),
+                has_more_messages: listing.is_some_and(|e| e.has_more),
+                message: self
+                    .account_messages
                     .read()
                     .await
                     .get(&login)
                     .unwrap_or(&None)
-                    .clone(),
-                message: self.message.read().await.clone(),
+                    .as_ref()
+                    .filter(|m| {
+                        Some(&m.folder) == selection.folder.as_ref()
+                            && Some(&m.id) == selection.message_id.as_ref()
+                    })
+                    .map(|m| m.text.clone()),
+                // A pane that is still loading shows a throbber, not the last error
+                error: if is_updating {
+                    None
+                } else {
+                    self.account_errors.read().await.get(&login).cloned()
+                },
+                notification: self.notifications.read().await.front().cloned(),
+                send_status: self.send_status.read().await.clone(),
+                is_updating,
             }
         } else {
             ViewState {
@@ -102,10 +494,160 @@ impl State {
                     .iter()
                     .map(|a| a.login.clone())
                     .collect(),
+                account_names: self
+                    .accounts
+                    .read()
+                    .await
+                    .iter()
+                    .map(|a| a.display_name.clone().unwrap_or_else(|| a.login.clone()))
+                    .collect(),
+                account_statuses: self.account_statuses_view().await,
                 folders: None,
+                saved_queries: Vec::new(),
                 messages: None,
+                messages_pages: 0,
+                messages_page_size: 0,
+                has_more_messages: false,
                 message: None,
+                error: None,
+                notification: self.notifications.read().await.front().cloned(),
+                send_status: self.send_status.read().await.clone(),
+                is_updating: false,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN: &str = "me@inbox.com";
+
+    fn envelopes(ids: std::ops::Range<usize>) -> Vec<Envelope> {
+        ids.map(|id| Envelope {
+            id: id.to_string(),
+            ..Default::default()
+        })
+        .collect()
+    }
+
+    fn page(folder: &str, page: usize, ids: std::ops::Range<usize>) -> FolderEnvelopes {
+        FolderEnvelopes::page(
+            folder.to_string(),
+            None,
+            Sort::default(),
+            2,
+            page,
+            envelopes(ids),
+        )
+    }
+
+    async fn listed(state: &State) -> Option<(Vec<String>, usize, bool)> {
+        state
+            .account_envelopes
+            .read()
+            .await
+            .get(LOGIN)?
+            .as_ref()
+            .map(|l| {
+                let ids = l.envelopes.iter().map(|e| e.id.clone()).collect();
+                (ids, l.pages, l.has_more)
+            })
+    }
+
+    #[tokio::test]
+    async fn the_next_page_is_appended() {
+        let state = State::default();
+
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 0, 0..2))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 1, 2..3))
+            .await;
+
+        assert_eq!(
+            listed(&state).await,
+            Some((vec!["0".into(), "1".into(), "2".into()], 2, false))
+        );
+    }
+
+    #[tokio::test]
+    async fn an_earlier_page_replaces_it_and_everything_after_it() {
+        let state = State::default();
+
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 0, 0..2))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 1, 2..4))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 2, 4..6))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 1, 3..5))
+            .await;
+
+        assert_eq!(
+            listed(&state).await,
+            Some((
+                vec!["0".into(), "1".into(), "3".into(), "4".into()],
+                2,
+                true
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn a_page_of_another_listing_is_dropped() {
+        let state = State::default();
+
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 0, 0..2))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("Archive", 1, 2..4))
+            .await;
+        state
+            .add_envelopes_page(LOGIN, page("INBOX", 3, 6..8))
+            .await;
+
+        assert_eq!(
+            listed(&state).await,
+            Some((vec!["0".into(), "1".into()], 1, true))
+        );
+
+        // A first page starts the listing over
+        state
+            .add_envelopes_page(LOGIN, page("Archive", 0, 4..5))
+            .await;
+
+        assert_eq!(listed(&state).await, Some((vec!["4".into()], 1, false)));
+    }
+
+    #[test]
+    fn a_search_is_paged_only_for_its_own_query_and_sort() {
+        let search = FolderSearch {
+            query: None,
+            sort: Sort::From,
+            envelopes: envelopes(0..5),
+        };
+
+        let ids = |page: Option<Vec<Envelope>>| {
+            page.map(|p| p.into_iter().map(|e| e.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            ids(search.page(&None, Sort::From, 1, 2)),
+            Some(vec!["2".into(), "3".into()])
+        );
+        assert_eq!(
+            ids(search.page(&None, Sort::From, 2, 2)),
+            Some(vec!["4".into()])
+        );
+        assert_eq!(ids(search.page(&None, Sort::From, 3, 2)), Some(vec![]));
+        assert_eq!(ids(search.page(&None, Sort::Subject, 1, 2)), None);
+    }
+}
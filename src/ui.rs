@@ -1,13 +1,17 @@
-use email_address::EmailAddress;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Margin},
     style::{Style, Stylize},
-    text::Line,
-    widgets::{Block, Clear, List, Paragraph, Row, Table},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, Paragraph, Row, Table, Wrap},
     Frame,
 };
 
-use crate::app::{App, SelectedWidget};
+use crate::{
+    app::{App, Prompt, SelectedWidget},
+    draft,
+    query::Sort,
+    state::{AccountStatus, SendStatus},
+};
 
 pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
     let layout_main = Layout::default()
@@ -34,94 +38,194 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
         ])
         .split(layout_working_area[1]);
 
-    let send_to_is_valid = EmailAddress::is_valid(&app.send_to);
-
-    let status_widget = Block::default().title(match app.selected_widget {
-        SelectedWidget::Accounts => Line::from(vec![
-            " Quit ".into(),
-            "<q>".blue().bold(),
-            "     ".into(),
-            " previous acc ".into(),
-            "<k> ".blue().bold(),
-            "<Up>".blue().bold(),
-            "     ".into(),
-            " next acc ".into(),
-            "<j> ".blue().bold(),
-            "<Down>".blue().bold(),
-            "     ".into(),
-            " send new ".into(),
-            "<s> ".blue().bold(),
-        ])
-        .centered(),
-        SelectedWidget::Folders => Line::from(vec![
-            " Quit ".into(),
-            "<q>".blue().bold(),
-            "     ".into(),
-            " previous folder ".into(),
-            "<k> ".blue().bold(),
-            "<Up>".blue().bold(),
-            "     ".into(),
-            " next folder ".into(),
-            "<j> ".blue().bold(),
-            "<Down>".blue().bold(),
-            "     ".into(),
-            " send new ".into(),
-            "<s> ".blue().bold(),
-        ])
-        .centered(),
-        SelectedWidget::Messages => Line::from(vec![
-            " Quit ".into(),
-            "<q>".blue().bold(),
-            "     ".into(),
-            " previous message ".into(),
-            "<k> ".blue().bold(),
-            "<Up>".blue().bold(),
-            "     ".into(),
-            " next message ".into(),
-            "<j> ".blue().bold(),
-            "<Down>".blue().bold(),
-            "     ".into(),
-            " previous page ".into(),
-            "<p> ".blue().bold(),
-            "<Left>".blue().bold(),
-            "     ".into(),
-            " next page ".into(),
-            "<n> ".blue().bold(),
-            "<Right>".blue().bold(),
-            "     ".into(),
-            " send new ".into(),
-            "<s> ".blue().bold(),
-        ])
-        .centered(),
-        SelectedWidget::Message => Line::from(vec![
-            " Quit ".into(),
-            "<q>".blue().bold(),
-            "     ".into(),
-            " send new ".into(),
-            "<s> ".blue().bold(),
-        ]),
-        SelectedWidget::Send => {
-            let mut words = vec![
-                " Close ".into(),
+    let status_widget = match &app.view_state.notification {
+        _ if app.prompt == Some(Prompt::ConfirmExpunge) => Block::default().title(
+            Line::from(vec![
+                format!(" Delete {} message(s) for good? ", app.target_rows().len())
+                    .red()
+                    .bold(),
+                "     ".into(),
+                " delete ".into(),
+                "<y>".blue().bold(),
+                "     ".into(),
+                " cancel ".into(),
+                "<any>".blue().bold(),
+            ])
+            .centered(),
+        ),
+        _ if app.move_picker_state.is_some() => Block::default().title(
+            Line::from(vec![
+                " move ".into(),
+                "<Enter>".blue().bold(),
+                "     ".into(),
+                " cancel ".into(),
                 "<Esc>".blue().bold(),
+            ])
+            .centered(),
+        ),
+        _ if app.prompt.is_some() => {
+            let (label, action) = match app.prompt {
+                Some(Prompt::SaveQuery) => (" Save search as ", " save "),
+                _ => (" /", " search "),
+            };
+
+            let mut words = vec![
+                label.blue().bold(),
+                app.prompt_input.clone().into(),
+                "_".slow_blink(),
                 "     ".into(),
-                " Next input ".into(),
-                "<Tab>".blue().bold(),
+                action.into(),
+                "<Enter>".blue().bold(),
                 "     ".into(),
-                " Previous input ".into(),
-                "<Shift + Tab>".blue().bold(),
+                " cancel ".into(),
+                "<Esc>".blue().bold(),
             ];
 
-            if send_to_is_valid {
-                words.extend(vec![
-                    "     ".into(),
-                    " Send ".into(),
-                    "<Enter>".blue().bold(),
-                ]);
+            if let Some(error) = &app.prompt_error {
+                words.push("     ".into());
+                words.push(error.clone().red().bold());
             }
-            Line::from(words)
+
+            Block::default().title(Line::from(words))
         }
-    });
+        Some(notification) if app.selected_widget != SelectedWidget::Send => Block::default()
+            .title(
+                Line::from(vec![
+                    notification.clone().red().bold(),
+                    "     ".into(),
+                    " dismiss ".into(),
+                    "<Esc>".blue().bold(),
+                ])
+                .centered(),
+            ),
+        _ => Block::default().title(match app.selected_widget {
+            SelectedWidget::Accounts => Line::from(vec![
+                " Quit ".into(),
+                "<q>".blue().bold(),
+                "     ".into(),
+                " previous acc ".into(),
+                "<k> ".blue().bold(),
+                "<Up>".blue().bold(),
+                "     ".into(),
+                " next acc ".into(),
+                "<j> ".blue().bold(),
+                "<Down>".blue().bold(),
+                "     ".into(),
+                " send new ".into(),
+                "<s> ".blue().bold(),
+            ])
+            .centered(),
+            SelectedWidget::Folders => Line::from(vec![
+                " Quit ".into(),
+                "<q>".blue().bold(),
+                "     ".into(),
+                " previous folder ".into(),
+                "<k> ".blue().bold(),
+                "<Up>".blue().bold(),
+                "     ".into(),
+                " next folder ".into(),
+                "<j> ".blue().bold(),
+                "<Down>".blue().bold(),
+                "     ".into(),
+                " send new ".into(),
+                "<s> ".blue().bold(),
+            ])
+            .centered(),
+            SelectedWidget::Messages => {
+                let mut words = vec![
+                    " Quit ".into(),
+                    "<q>".blue().bold(),
+                    "     ".into(),
+                    " previous message ".into(),
+                    "<k> ".blue().bold(),
+                    "<Up>".blue().bold(),
+                    "     ".into(),
+                    " next message ".into(),
+                    "<j> ".blue().bold(),
+                    "<Down>".blue().bold(),
+                    "     ".into(),
+                    " search ".into(),
+                    "</>".blue().bold(),
+                    "     ".into(),
+                    " sort ".into(),
+                    "<o>".blue().bold(),
+                    "     ".into(),
+                    " read/unread ".into(),
+                    "<u>".blue().bold(),
+                    "     ".into(),
+                    " flag ".into(),
+                    "<f>".blue().bold(),
+                    "     ".into(),
+                    " answered ".into(),
+                    "<a>".blue().bold(),
+                    "     ".into(),
+                    " delete ".into(),
+                    "<d> ".blue().bold(),
+                    "<D>".blue().bold(),
+                    "     ".into(),
+                    " archive ".into(),
+                    "<e>".blue().bold(),
+                    "     ".into(),
+                    " move ".into(),
+                    "<m>".blue().bold(),
+                    "     ".into(),
+                    " mark ".into(),
+                    "<Space> ".blue().bold(),
+                    "<v> ".blue().bold(),
+                    "<*>".blue().bold(),
+                ];
+
+                if app.search.is_some() {
+                    words.push("     ".into());
+                    words.push(" save search ".into());
+                    words.push("<S>".blue().bold());
+                }
+
+                words.push("     ".into());
+                words.push(" send new ".into());
+                words.push("<s> ".blue().bold());
+
+                Line::from(words).centered()
+            }
+            SelectedWidget::Message => Line::from(vec![
+                " Quit ".into(),
+                "<q>".blue().bold(),
+                "     ".into(),
+                " send new ".into(),
+                "<s> ".blue().bold(),
+                "     ".into(),
+                " reply ".into(),
+                "<r>".blue().bold(),
+                "     ".into(),
+                " reply all ".into(),
+                "<R>".blue().bold(),
+                "     ".into(),
+                " forward ".into(),
+                "<f>".blue().bold(),
+            ]),
+            SelectedWidget::Send => {
+                let mut words = vec![
+                    " Close ".into(),
+                    "<Esc>".blue().bold(),
+                    "     ".into(),
+                    " Next input ".into(),
+                    "<Tab>".blue().bold(),
+                    "     ".into(),
+                    " Previous input ".into(),
+                    "<Shift + Tab>".blue().bold(),
+                ];
+
+                if app.is_send_form_valid() && !app.is_awaiting_send {
+                    words.extend(vec![
+                        "     ".into(),
+                        " Send ".into(),
+                        "<Enter>".blue().bold(),
+                    ]);
+                }
+                Line::from(words)
+            }
+        }),
+    };
     let accounts_block =
         Block::bordered()
             .title("[1] Accounts")
@@ -130,7 +234,21 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
                 _ => Style::default(),
             });
 
-    let accounts_list = List::new(app.view_state.accounts.clone())
+    let accounts_items = app
+        .view_state
+        .account_names
+        .iter()
+        .zip(app.view_state.account_statuses.iter())
+        .map(|(name, status)| match status {
+            AccountStatus::Connecting => {
+                Line::from(vec![name.clone().into(), " connecting".yellow()])
+            }
+            AccountStatus::Ready => Line::from(name.clone()),
+            AccountStatus::Failed(_) => Line::from(vec![name.clone().into(), " failed".red()]),
+        })
+        .collect::<Vec<Line>>();
+
+    let accounts_list = List::new(accounts_items)
         .block(accounts_block)
         .highlight_style(Style::new().black().bg(ratatui::style::Color::Gray));
 
@@ -142,13 +260,20 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
                 _ => Style::default(),
             });
 
-    let messages_block =
-        Block::bordered()
-            .title("[3] Messages")
-            .border_style(match app.selected_widget {
-                SelectedWidget::Messages => Style::new().green().bold(),
-                _ => Style::default(),
-            });
+    // Cached messages stay visible while they are reconciled with the server
+    let sync_status = if app.view_state.is_updating {
+        Line::from(" syncing ".yellow())
+    } else {
+        Line::default()
+    };
+
+    let messages_block = Block::bordered()
+        .title("[3] Messages")
+        .title(sync_status.right_aligned())
+        .border_style(match app.selected_widget {
+            SelectedWidget::Messages => Style::new().green().bold(),
+            _ => Style::default(),
+        });
 
     let message_block =
         Block::bordered()
@@ -174,35 +299,66 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
     );
 
     if let Some(folders) = &app.view_state.folders {
-        let folders_list = List::new(folders.clone())
-            .block(folders_block)
-            .highlight_style(Style::new().black().bg(ratatui::style::Color::Gray));
+        let saved_queries = app
+            .view_state
+            .saved_queries
+            .iter()
+            .map(|q| Line::from(vec!["/ ".blue(), q.name.clone().into()]));
+
+        let folders_list = List::new(
+            folders
+                .iter()
+                .map(|f| Line::from(f.clone()))
+                .chain(saved_queries),
+        )
+        .block(folders_block)
+        .highlight_style(Style::new().black().bg(ratatui::style::Color::Gray));
 
         frame.render_stateful_widget(
             folders_list,
             layout_left_tower[1],
             &mut app.folders_list_state,
         );
-    } else {
+    } else if let Some(error) = &app.view_state.error {
+        frame.render_widget(
+            Paragraph::new(error.clone())
+                .red()
+                .wrap(Wrap { trim: true })
+                .block(folders_block),
+            layout_left_tower[1],
+        );
+    } else if app.is_loading_folders() {
         frame.render_widget(folders_block, layout_left_tower[1]);
 
         frame.render_widget(
             throbber_widgets_tui::Throbber::default(),
             layout_left_tower[1].inner(Margin::new(1, 1)),
         );
+    } else {
+        frame.render_widget(folders_block, layout_left_tower[1]);
     };
 
+    // Borders, header and footer
+    app.messages_table_rows = layout_rigth_tower[0].height.saturating_sub(4) as usize;
+
     if let Some(messages) = &app.view_state.messages {
         let rows = messages
             .iter()
-            .map(|e| {
-                Row::new(vec![
+            .enumerate()
+            .map(|(idx, e)| {
+                let row = Row::new(vec![
                     e.id.clone(),
                     e.flags.to_string(),
                     e.subject.clone(),
                     e.from.to_string(),
                     e.date.to_string(),
-                ])
+                ]);
+
+                if app.is_marked(idx) {
+                    row.yellow().bold()
+                } else {
+                    row
+                }
             })
             .collect::<Vec<Row>>();
         let widths = [
@@ -212,13 +368,32 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
             Constraint::Ratio(2, 18),
             Constraint::Ratio(2, 18),
         ];
+        let table_sort = app.table_sort();
+
         let messages_table = Table::new(rows, widths)
-            .header(Row::new(vec!["id", "flags", "subject", "from", "date"]))
+            .header(Row::new(vec![
+                "id",
+                "flags",
+                match table_sort {
+                    Some(Sort::Subject) => "subject ▲",
+                    _ => "subject",
+                },
+                match table_sort {
+                    Some(Sort::From) => "from ▲",
+                    _ => "from",
+                },
+                match table_sort {
+                    Some(Sort::DateDesc) => "date ▼",
+                    Some(Sort::DateAsc) => "date ▲",
+                    _ => "date",
+                },
+            ]))
             .block(messages_block)
-            .footer(Row::new(vec![format!(
-                "page: {}",
-                app.messages_table_page.to_string()
-            )]))
+            .footer(Row::new(vec![if app.is_loading_more_messages() {
+                "loading".to_string()
+            } else {
+                format!("{} rows", messages.len())
+            }]))
             .row_highlight_style(Style::new().black().bg(ratatui::style::Color::Gray));
 
         frame.render_stateful_widget(
@@ -226,13 +401,23 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
             layout_rigth_tower[0],
             &mut app.messages_table_state,
         );
-    } else {
+    } else if let Some(error) = &app.view_state.error {
+        frame.render_widget(
+            Paragraph::new(error.clone())
+                .red()
+                .wrap(Wrap { trim: true })
+                .block(messages_block),
+            layout_rigth_tower[0],
+        );
+    } else if app.is_loading_messages() {
         frame.render_widget(messages_block, layout_rigth_tower[0]);
 
         frame.render_widget(
             throbber_widgets_tui::Throbber::default(),
             layout_rigth_tower[0].inner(Margin::new(1, 1)),
         );
+    } else {
+        frame.render_widget(messages_block, layout_rigth_tower[0]);
     };
 
     if let Some(message) = &app.view_state.message {
@@ -243,18 +428,43 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
         };
 
         frame.render_widget(message_p, layout_rigth_tower[1]);
-    } else {
+    } else if let Some(error) = &app.view_state.error {
+        frame.render_widget(
+            Paragraph::new(error.clone())
+                .red()
+                .wrap(Wrap { trim: true })
+                .block(message_block),
+            layout_rigth_tower[1],
+        );
+    } else if app.is_loading_message() {
         frame.render_widget(message_block, layout_rigth_tower[1]);
 
         frame.render_widget(
             throbber_widgets_tui::Throbber::default(),
             layout_rigth_tower[1].inner(Margin::new(1, 1)),
         );
+    } else {
+        frame.render_widget(message_block, layout_rigth_tower[1]);
     }
 
     frame.render_widget(ads_block, layout_rigth_tower[2]);
     frame.render_widget(status_widget, layout_main[1]);
 
+    if let (Some(picker), Some(folders)) = (&mut app.move_picker_state, &app.view_state.folders) {
+        let area = layout_rigth_tower[0].inner(Margin::new(layout_rigth_tower[0].width / 4, 1));
+
+        let folders_list = List::new(folders.iter().map(|f| Line::from(f.clone())))
+            .block(
+                Block::bordered()
+                    .title("Move to")
+                    .border_style(Style::new().green().bold()),
+            )
+            .highlight_style(Style::new().black().bg(ratatui::style::Color::Gray));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(folders_list, area, picker);
+    }
+
     if app.selected_widget == SelectedWidget::Send {
         let Some(selected_account_idx) = app.accounts_list_selected else {
             return;
@@ -268,9 +478,21 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
 
         frame.render_widget(Clear, layout_main[0]);
 
+        let send_status = match &app.view_state.send_status {
+            Some(SendStatus::Sending) if app.show_send_status => {
+                Line::from(" Sending... ".yellow())
+            }
+            Some(SendStatus::Sent) if app.show_send_status => Line::from(" Sent ".green().bold()),
+            Some(SendStatus::Failed(error)) if app.show_send_status => {
+                Line::from(format!(" Failed: {} ", error).red().bold())
+            }
+            _ => Line::default(),
+        };
+
         frame.render_widget(
             Block::bordered()
-                .title(Line::from(format!("Send new message from: {}", login)).left_aligned()),
+                .title(Line::from(format!("Send new message from: {}", login)).left_aligned())
+                .title(send_status.right_aligned()),
             layout_main[0],
         );
 
@@ -279,30 +501,50 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
         let inner_layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Ratio(1, 9),
-                Constraint::Ratio(2, 9),
-                Constraint::Ratio(6, 9),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(3),
             ])
             .split(inner_area);
 
         frame.render_widget(
-            Paragraph::new(app.send_to.clone())
-                .block(
-                    Block::bordered()
-                        .title("To")
-                        .border_style(match app.selected_send_widget {
-                            crate::app::SelectedSendWidget::To => Style::new().green().bold(),
-                            _ => Style::default(),
-                        }),
-                )
-                .style(if send_to_is_valid {
-                    Style::default().green()
-                } else {
-                    Style::default().red()
-                }),
+            Paragraph::new(recipients_line(&app.send_to)).block(
+                Block::bordered()
+                    .title("To")
+                    .border_style(match app.selected_send_widget {
+                        crate::app::SelectedSendWidget::To => Style::new().green().bold(),
+                        _ => Style::default(),
+                    }),
+            ),
             inner_layout[0],
         );
 
+        frame.render_widget(
+            Paragraph::new(recipients_line(&app.send_cc)).block(
+                Block::bordered()
+                    .title("Cc")
+                    .border_style(match app.selected_send_widget {
+                        crate::app::SelectedSendWidget::Cc => Style::new().green().bold(),
+                        _ => Style::default(),
+                    }),
+            ),
+            inner_layout[1],
+        );
+
+        frame.render_widget(
+            Paragraph::new(recipients_line(&app.send_bcc)).block(
+                Block::bordered()
+                    .title("Bcc")
+                    .border_style(match app.selected_send_widget {
+                        crate::app::SelectedSendWidget::Bcc => Style::new().green().bold(),
+                        _ => Style::default(),
+                    }),
+            ),
+            inner_layout[2],
+        );
+
         frame.render_widget(
             Paragraph::new(app.send_subject.clone()).block(
                 Block::bordered()
@@ -312,7 +554,7 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
                         _ => Style::default(),
                     }),
             ),
-            inner_layout[1],
+            inner_layout[3],
         );
 
         frame.render_widget(
@@ -324,7 +566,30 @@ pub fn ui(frame: &mut Frame<'_>, app: &mut App) {
                         _ => Style::default(),
                     }),
             ),
-            inner_layout[2],
+            inner_layout[4],
         );
     }
 }
+
+// Each recipient is colored on its own so a typo stands out in a long list
+fn recipients_line(text: &str) -> Line<'_> {
+    let mut spans = Vec::new();
+
+    for (idx, entry) in draft::split_recipients(text).into_iter().enumerate() {
+        if idx > 0 {
+            spans.push(Span::raw(","));
+        }
+
+        let span = Span::raw(entry);
+
+        spans.push(if entry.trim().is_empty() {
+            span
+        } else if draft::parse_mailbox(entry).is_some() {
+            span.green()
+        } else {
+            span.red()
+        });
+    }
+
+    Line::from(spans)
+}